@@ -0,0 +1,121 @@
+// Renders a seed's world to a PNG without starting the game
+//
+// cargo run --bin export_png -- <seed> [--out map.png] [--preset continent]
+//     [--width 1000] [--height 1000] [--towers] [--treasures] [--rivers] [--all]
+
+use image::{Rgb, RgbImage};
+
+use ldjam55::*;
+
+const RIVER_COLOR: Rgb<u8> = Rgb([90, 170, 255]);
+const TREASURE_COLOR: Rgb<u8> = Rgb([255, 210, 0]);
+const PLAYER_TOWER_COLOR: Rgb<u8> = Rgb([255, 255, 255]);
+const ENEMY_TOWER_COLOR: Rgb<u8> = Rgb([220, 30, 30]);
+
+struct Args {
+    world: WorldArgs,
+    out: String,
+    towers: bool,
+    treasures: bool,
+    rivers: bool,
+}
+
+const USAGE: &str = "Usage: export_png <seed> [--out map.png] [--preset name] [--width n] \
+                     [--height n] [--towers] [--treasures] [--rivers] [--all]";
+
+fn parse_args() -> Args {
+    let mut out = None;
+    let (mut towers, mut treasures, mut rivers) = (false, false, false);
+
+    let world = parse_world_args(|flag, rest| {
+        match flag {
+            "--out" => out = Some(flag_value(flag, rest)?),
+            "--towers" => towers = true,
+            "--treasures" => treasures = true,
+            "--rivers" => rivers = true,
+            "--all" => (towers, treasures, rivers) = (true, true, true),
+            _ => return Ok(false),
+        }
+        Ok(true)
+    })
+    .unwrap_or_else(|e| exit_with_usage(e, USAGE));
+
+    let out = out.unwrap_or_else(|| format!("seed-{}.png", world.seed.trim().replace(' ', "_")));
+
+    Args {
+        world,
+        out,
+        towers,
+        treasures,
+        rivers,
+    }
+}
+
+fn main() {
+    let args = parse_args();
+
+    let config = args.world.config();
+    let world = WorldGenerator::generate(&WorldGenParams::from_config(&config));
+    let image = render(&world, &args);
+
+    if let Err(e) = image.save(&args.out) {
+        eprintln!("Unable to write {}: {}", args.out, e);
+        std::process::exit(1);
+    }
+
+    println!(
+        "Seed {} ({}) written to {}",
+        config.seed_text,
+        config.rng_seed(),
+        args.out
+    );
+}
+
+fn render(world: &GeneratedWorld, args: &Args) -> RgbImage {
+    let (width, height) = world.map.size();
+    let (width, height) = (width as u32, height as u32);
+
+    // Tile y goes up, image y goes down
+    let flip = |y: u32| height - 1 - y;
+
+    let mut image = RgbImage::from_fn(width, height, |x, y| {
+        let val = world.map.get_value(x as usize, flip(y) as usize);
+        Rgb(index_color(get_index(val)))
+    });
+
+    if args.rivers {
+        for y in 0..height {
+            for x in 0..width {
+                if world.rivers.is_water(x, y) {
+                    image.put_pixel(x, flip(y), RIVER_COLOR);
+                }
+            }
+        }
+    }
+
+    if args.treasures {
+        for (x, y) in world.treasure_spots.iter() {
+            draw_marker(&mut image, *x, flip(*y), 1, TREASURE_COLOR);
+        }
+    }
+
+    if args.towers {
+        for (x, y) in world.enemy_towers.iter() {
+            draw_marker(&mut image, *x, flip(*y), 3, ENEMY_TOWER_COLOR);
+        }
+        let (x, y) = world.player_tower;
+        draw_marker(&mut image, x, flip(y), 4, PLAYER_TOWER_COLOR);
+    }
+
+    image
+}
+
+// Square so single tiles are visible on a 1000x1000 map
+fn draw_marker(image: &mut RgbImage, x: u32, y: u32, radius: u32, color: Rgb<u8>) {
+    let (width, height) = image.dimensions();
+    for px in x.saturating_sub(radius)..=(x + radius).min(width - 1) {
+        for py in y.saturating_sub(radius)..=(y + radius).min(height - 1) {
+            image.put_pixel(px, py, color);
+        }
+    }
+}
@@ -0,0 +1,77 @@
+// Prints everything a seed generates (towers, treasure contents) without starting the game
+//
+// cargo run --bin inspect_seed -- <seed> [--format yaml|json] [--preset continent]
+//     [--width 1000] [--height 1000] [--check]
+//
+// --check exits with 2 when the map failed its quality checks, for scripting
+
+use ldjam55::*;
+
+enum Format {
+    Yaml,
+    Json,
+}
+
+struct Args {
+    world: WorldArgs,
+    format: Format,
+    check: bool,
+}
+
+const USAGE: &str = "Usage: inspect_seed <seed> [--format yaml|json] [--preset name] [--width n] \
+                     [--height n] [--check]";
+
+fn parse_args() -> Args {
+    let mut format = Format::Yaml;
+    let mut check = false;
+
+    let world = parse_world_args(|flag, rest| {
+        match flag {
+            "--format" => {
+                format = match flag_value(flag, rest)?.as_str() {
+                    "yaml" => Format::Yaml,
+                    "json" => Format::Json,
+                    other => return Err(ArgError::Unknown(format!("{} {}", flag, other))),
+                }
+            }
+            "--json" => format = Format::Json,
+            "--check" => check = true,
+            _ => return Ok(false),
+        }
+        Ok(true)
+    })
+    .unwrap_or_else(|e| exit_with_usage(e, USAGE));
+
+    Args {
+        world,
+        format,
+        check,
+    }
+}
+
+fn main() {
+    let args = parse_args();
+
+    let config = args.world.config();
+
+    let params = WorldGenParams::from_config(&config);
+    let world = WorldGenerator::generate(&params);
+    let summary = WorldSummary::new(&config, &params, &world);
+
+    let output = match args.format {
+        Format::Yaml => serde_yaml::to_string(&summary).map_err(|e| e.to_string()),
+        Format::Json => serde_json::to_string_pretty(&summary).map_err(|e| e.to_string()),
+    };
+
+    match output {
+        Ok(output) => println!("{}", output),
+        Err(e) => {
+            eprintln!("Unable to serialize seed {}: {}", config.seed_text, e);
+            std::process::exit(1);
+        }
+    }
+
+    if args.check && !summary.quality.is_acceptable() {
+        std::process::exit(2);
+    }
+}
@@ -8,7 +8,6 @@ use bevy_inspector_egui::quick::WorldInspectorPlugin;
 use bevy_mod_picking::prelude::*;
 use bevy_prng::WyRand;
 use bevy_rand::prelude::*;
-use wyrand;
 
 #[cfg(debug_assertions)]
 use bevy::diagnostic::{FrameTimeDiagnosticsPlugin, LogDiagnosticsPlugin};
@@ -17,13 +16,19 @@ use ldjam55::*;
 use rand::SeedableRng;
 
 fn main() {
+    let mut config = GameConfig::default();
+    if let Some(seed) = seed_from_args_or_env() {
+        config.set_seed(&seed);
+        config.ask_for_seed = false;
+    }
+
     let mut app = App::new();
     // .add_plugins(DefaultPlugins.set(low_latency_window_plugin()))
     // Normally MSAA 4 but from the template (for web? I suspect) we turn it off
     app
         // Engine-level Resources
         .insert_resource(Msaa::Off)
-        .insert_resource(GameConfig::default())
+        .insert_resource(config)
         .insert_resource(AssetMetaCheck::Never)
         .insert_resource(ClearColor(Color::rgb(0.4, 0.4, 0.4)))
         // Plugins
@@ -55,9 +60,11 @@ fn main() {
         .insert_resource(SelectedUnit::default())
         // Systems
         .add_systems(Startup, setup)
+        .add_systems(OnEnter(Game::MapGeneration), setup_rng_streams)
         .add_systems(PreUpdate, camera_control)
         .add_systems(PreUpdate, update_cursor_pos)
         // Game plugins
+        .add_plugins(SeedUiPlugin)
         .add_plugins(MapGenerationPlugin)
         .add_plugins(UnitsUiPlugin {
             state: Game::Playing,
@@ -65,10 +72,36 @@ fn main() {
         .add_plugins(UnitsPlugin {
             state: Game::Playing,
         })
+        .add_plugins(CombatPlugin {
+            state: Game::Playing,
+        })
+        .add_plugins(EnemiesPlugin {
+            state: Game::Playing,
+        })
         .add_plugins(MapInteractionPlugin {
             state: Game::Playing,
         })
-        .add_plugins(TreasureGenerationPlugin);
+        .add_plugins(FogOfWarPlugin {
+            state: Game::Playing,
+        })
+        .add_plugins(MinimapPlugin {
+            state: Game::Playing,
+        })
+        .add_plugins(SelectionPlugin {
+            state: Game::Playing,
+        })
+        .add_plugins(StockpileUiPlugin {
+            state: Game::Playing,
+        })
+        .add_plugins(NoticesUiPlugin {
+            state: Game::Playing,
+        })
+        .add_plugins(TerritoryPlugin {
+            state: Game::Playing,
+        })
+        .add_plugins(TerrainPlugin {
+            state: Game::Playing,
+        });
 
     #[cfg(debug_assertions)]
     {
@@ -111,8 +144,7 @@ fn setup(
     mut texture_atlases: ResMut<Assets<TextureAtlasLayout>>,
 ) {
     let texture_handle: Handle<Image> = asset_server.load("tiles.png");
-    let layout = TextureAtlasLayout::from_grid(Vec2::new(32.0, 32.0), 12, 1, None, None);
-    let texture_atlas_layout = texture_atlases.add(layout);
+    let texture_atlas_layout = texture_atlases.add(TILESET.atlas_layout(config.tile_size));
     assets.tiles = texture_handle;
     assets.tiles_layout = texture_atlas_layout;
     assets.font = asset_server.load("fonts/MonaspaceRadon-Regular.otf");
@@ -139,15 +171,13 @@ fn setup(
     });
 
     commands.spawn(Camera2dBundle::default());
-    // Take u32 twice and convert to u64 from config.seed
-    // Using mem::transmute to convert u32 to u64
-    let seed: [u32; 2] = [config.seed; 2];
-    let seed: u64 = unsafe { std::mem::transmute(seed) };
-
-    commands.insert_resource(GlobalEntropy::new(WyRand::new(wyrand::WyRand::new(seed))));
     log::info!("Setup complete");
 
-    game.set(Game::MapGeneration);
+    if config.ask_for_seed {
+        game.set(Game::SeedEntry);
+    } else {
+        game.set(Game::MapGeneration);
+    }
 }
 
 fn camera_control(
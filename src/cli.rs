@@ -0,0 +1,215 @@
+// Argument parsing shared by the command line tools (export_png, inspect_seed)
+
+use crate::*;
+
+// Smaller than this and there's no room to place towers, zero panics outright
+pub const MIN_MAP_SIZE: u32 = 32;
+
+// What every tool takes: <seed> [--preset name] [--width n] [--height n]
+#[derive(Debug, Clone, PartialEq)]
+pub struct WorldArgs {
+    pub seed: String,
+    pub preset: String,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl WorldArgs {
+    pub fn config(&self) -> GameConfig {
+        let mut config = GameConfig {
+            map_width: self.width,
+            map_height: self.height,
+            preset: self.preset.clone(),
+            ..Default::default()
+        };
+        config.set_seed(&self.seed);
+        config
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ArgError {
+    Help,
+    NoSeed,
+    MissingValue(String),
+    NotANumber(String, String),
+    TooSmall(String, u32),
+    Unknown(String),
+}
+
+impl std::fmt::Display for ArgError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ArgError::Help => Ok(()),
+            ArgError::NoSeed => write!(f, "No seed given and {} isn't set", SEED_ENV_VAR),
+            ArgError::MissingValue(flag) => write!(f, "{} needs a value", flag),
+            ArgError::NotANumber(flag, value) => write!(f, "{} {} isn't a number", flag, value),
+            ArgError::TooSmall(flag, value) => write!(
+                f,
+                "{} {} is too small, maps are at least {}",
+                flag, value, MIN_MAP_SIZE
+            ),
+            ArgError::Unknown(arg) => write!(f, "Unknown option {}", arg),
+        }
+    }
+}
+
+impl std::error::Error for ArgError {}
+
+pub fn exit_with_usage(error: ArgError, usage: &str) -> ! {
+    if error != ArgError::Help {
+        eprintln!("{}", error);
+    }
+    eprintln!("{}", usage);
+    std::process::exit(1);
+}
+
+// Each flag goes to `tool_flag` first, which can take its value from `rest` and
+// returns false for flags it doesn't know. The seed falls back to SEED_ENV_VAR
+// like the game does.
+pub fn parse_world_args<F>(tool_flag: F) -> Result<WorldArgs, ArgError>
+where
+    F: FnMut(&str, &mut dyn Iterator<Item = String>) -> Result<bool, ArgError>,
+{
+    parse_world_args_from(
+        std::env::args().skip(1),
+        std::env::var(SEED_ENV_VAR).ok(),
+        tool_flag,
+    )
+}
+
+fn parse_world_args_from<F>(
+    mut args: impl Iterator<Item = String>,
+    env_seed: Option<String>,
+    mut tool_flag: F,
+) -> Result<WorldArgs, ArgError>
+where
+    F: FnMut(&str, &mut dyn Iterator<Item = String>) -> Result<bool, ArgError>,
+{
+    let config = GameConfig::default();
+    let mut preset = config.preset;
+    let mut width = config.map_width;
+    let mut height = config.map_height;
+    let mut seed = None;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--preset" => preset = flag_value(&arg, &mut args)?,
+            "--width" => width = map_size(&arg, &mut args)?,
+            "--height" => height = map_size(&arg, &mut args)?,
+            "-h" | "--help" => return Err(ArgError::Help),
+            _ if arg.starts_with("--") => {
+                if !tool_flag(&arg, &mut args)? {
+                    return Err(ArgError::Unknown(arg));
+                }
+            }
+            _ => seed = Some(arg),
+        }
+    }
+
+    let seed = seed
+        .or(env_seed)
+        .filter(|seed| !seed.trim().is_empty())
+        .ok_or(ArgError::NoSeed)?;
+
+    Ok(WorldArgs {
+        seed,
+        preset,
+        width,
+        height,
+    })
+}
+
+pub fn flag_value(flag: &str, rest: &mut dyn Iterator<Item = String>) -> Result<String, ArgError> {
+    rest.next()
+        .filter(|value| !value.starts_with("--"))
+        .ok_or_else(|| ArgError::MissingValue(flag.to_string()))
+}
+
+fn map_size(flag: &str, rest: &mut dyn Iterator<Item = String>) -> Result<u32, ArgError> {
+    let value = flag_value(flag, rest)?;
+    let size: u32 = value
+        .parse()
+        .map_err(|_| ArgError::NotANumber(flag.to_string(), value))?;
+    if size < MIN_MAP_SIZE {
+        return Err(ArgError::TooSmall(flag.to_string(), size));
+    }
+    Ok(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &str, env_seed: Option<&str>) -> Result<WorldArgs, ArgError> {
+        let args = args.split_whitespace().map(String::from);
+        parse_world_args_from(args, env_seed.map(String::from), |flag, _| {
+            Ok(flag == "--towers")
+        })
+    }
+
+    #[test]
+    fn seed_and_sizes() {
+        let args = parse(
+            "442 --width 64 --towers --height 128 --preset highlands",
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            args,
+            WorldArgs {
+                seed: "442".to_string(),
+                preset: "highlands".to_string(),
+                width: 64,
+                height: 128,
+            }
+        );
+
+        let defaults = GameConfig::default();
+        let args = parse("442", None).unwrap();
+        assert_eq!(
+            (args.width, args.height),
+            (defaults.map_width, defaults.map_height)
+        );
+    }
+
+    #[test]
+    fn seed_falls_back_to_the_env() {
+        assert_eq!(parse("", Some("from env")).unwrap().seed, "from env");
+        assert_eq!(parse("442", Some("from env")).unwrap().seed, "442");
+        assert_eq!(parse("", None), Err(ArgError::NoSeed));
+        assert_eq!(parse("", Some(" ")), Err(ArgError::NoSeed));
+    }
+
+    #[test]
+    fn sizes_that_would_not_generate_are_rejected() {
+        assert_eq!(
+            parse("442 --width 0", None),
+            Err(ArgError::TooSmall("--width".to_string(), 0))
+        );
+        assert_eq!(
+            parse("442 --height 8", None),
+            Err(ArgError::TooSmall("--height".to_string(), 8))
+        );
+        assert_eq!(
+            parse("442 --width wide", None),
+            Err(ArgError::NotANumber(
+                "--width".to_string(),
+                "wide".to_string()
+            ))
+        );
+        assert_eq!(
+            parse("442 --width", None),
+            Err(ArgError::MissingValue("--width".to_string()))
+        );
+    }
+
+    #[test]
+    fn unknown_flags_and_help() {
+        assert_eq!(
+            parse("442 --rivers", None),
+            Err(ArgError::Unknown("--rivers".to_string()))
+        );
+        assert_eq!(parse("442 --help", None), Err(ArgError::Help));
+    }
+}
@@ -9,12 +9,16 @@ pub use noise::utils::NoiseMap;
 pub use rand::prelude::{IteratorRandom, Rng};
 pub use xxhash_rust::xxh3::xxh3_64;
 
+pub mod cli;
 pub mod map;
+pub mod rng;
 pub mod treasures;
 pub mod ui;
 pub mod units;
 
+pub use cli::*;
 pub use map::*;
+pub use rng::*;
 pub use treasures::*;
 pub use ui::*;
 pub use units::*;
@@ -23,6 +27,7 @@ pub use units::*;
 pub enum Game {
     #[default]
     Startup,
+    SeedEntry,
     MapGeneration,
     Loading, // Here, but dunno if we need it
     Playing,
@@ -30,9 +35,20 @@ pub enum Game {
     Paused,
 }
 
+// Seeds can come from `--seed <text>` or this, otherwise they're typed in on startup
+pub const SEED_ENV_VAR: &str = "LDJAM55_SEED";
+
 #[derive(Resource)]
 pub struct GameConfig {
-    pub seed: u32,
+    // What the player typed, shown in game so maps can be shared
+    pub seed_text: String,
+    // Show the seed entry screen before generating the map
+    pub ask_for_seed: bool,
+    pub map_width: u32,
+    pub map_height: u32,
+    pub tile_size: f32,
+    // Name of a world generation preset in assets/worldgen
+    pub preset: String,
 }
 
 #[derive(Component)]
@@ -47,6 +63,10 @@ pub struct MapFogOfWar;
 #[derive(Component)]
 pub struct PlayerTower;
 
+// Every tower tile, the player's included, tinted by whoever holds it
+#[derive(Component)]
+pub struct TowerTile;
+
 #[derive(Resource)]
 pub struct TreasureLocs {
     pub locs: Vec<(u32, u32)>,
@@ -58,10 +78,29 @@ pub struct GameState {
     pub player_tower_location: (u32, u32),
     pub enemy_tower_locations: Vec<(u32, u32)>,
     pub map: NoiseMap,
+    pub biomes: BiomeMap,
+    pub rivers: RiverNetwork,
     pub score: u64,
     pub units: [UnitEntry; 7],
     pub treasures_found: Vec<Treasure>,
     pub unit_boons: [TotalBoons; 8],
+    pub stockpile: Stockpile,
+}
+
+// What excavators have mined out of deposits so far
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Stockpile {
+    pub ore: f32,
+    pub crystal: f32,
+}
+
+impl Stockpile {
+    pub fn add(&mut self, kind: DepositKind, amount: f32) {
+        match kind {
+            DepositKind::Ore => self.ore += amount,
+            DepositKind::Crystal => self.crystal += amount,
+        }
+    }
 }
 
 #[derive(Default)]
@@ -82,7 +121,10 @@ impl Default for GameState {
             enemy_tower_locations: vec![],
             treasures_found: vec![],
             map: NoiseMap::default(),
+            biomes: BiomeMap::default(),
+            rivers: RiverNetwork::default(),
             score: 0,
+            stockpile: Stockpile::default(),
             units: [
                 UnitEntry::Available,
                 UnitEntry::Unavailable,
@@ -129,8 +171,69 @@ pub struct Icons {
 
 impl Default for GameConfig {
     fn default() -> Self {
-        Self { seed: 442 }
+        Self {
+            seed_text: "442".to_string(),
+            ask_for_seed: true,
+            map_width: 1000,
+            map_height: 1000,
+            tile_size: 32.0,
+            preset: "continent".to_string(),
+        }
+    }
+}
+
+impl GameConfig {
+    pub fn set_seed(&mut self, text: &str) {
+        self.seed_text = text.trim().to_string();
+    }
+
+    // Full 64 bits, every RngStream is forked from this
+    pub fn rng_seed(&self) -> u64 {
+        xxh3_64(self.seed_text.as_bytes())
+    }
+
+    pub fn map_size(&self) -> TilemapSize {
+        TilemapSize {
+            x: self.map_width,
+            y: self.map_height,
+        }
+    }
+
+    pub fn tilemap_tile_size(&self) -> TilemapTileSize {
+        TilemapTileSize {
+            x: self.tile_size,
+            y: self.tile_size,
+        }
+    }
+}
+
+// `--seed <text>` or `--seed=<text>` wins over the environment variable
+pub fn seed_from_args_or_env() -> Option<String> {
+    seed_from_args(std::env::args().skip(1)).or_else(|| {
+        std::env::var(SEED_ENV_VAR)
+            .ok()
+            .filter(|seed| !seed.trim().is_empty())
+    })
+}
+
+fn seed_from_args(mut args: impl Iterator<Item = String>) -> Option<String> {
+    while let Some(arg) = args.next() {
+        let seed = if arg == "--seed" {
+            args.next().filter(|seed| !seed.starts_with("--"))
+        } else if let Some(seed) = arg.strip_prefix("--seed=") {
+            Some(seed.to_string())
+        } else {
+            continue;
+        };
+
+        // Logging isn't up yet this early, so straight to stderr
+        if seed.as_ref().map_or(true, |seed| seed.trim().is_empty()) {
+            eprintln!("--seed needs a value, falling back to {}", SEED_ENV_VAR);
+            return None;
+        }
+        return seed;
     }
+    None
 }
 
 #[derive(Resource)]
@@ -154,9 +257,176 @@ impl Default for CursorPos {
 
 #[derive(Resource, Default)]
 pub struct SelectedUnit {
-    pub unit: Option<u8>, // slot
+    pub unit: Option<u8>, // slot, the one shown in the unit panel
     pub e: Option<Entity>,
+    // Everything selected (slot, entity), orders go to all of them
+    pub units: Vec<(u8, Entity)>,
+}
+
+impl SelectedUnit {
+    pub fn select(&mut self, slot: u8, e: Entity) {
+        self.unit = Some(slot);
+        self.e = Some(e);
+        self.units = vec![(slot, e)];
+    }
+
+    // Ctrl-click, adds or removes one unit and keeps the rest
+    pub fn toggle(&mut self, slot: u8, e: Entity) {
+        if self.is_selected(slot) {
+            self.remove(slot);
+            return;
+        }
+
+        self.units.push((slot, e));
+        self.units.sort_by_key(|(slot, _)| *slot);
+        if !self.unit.is_some_and(|unit| self.is_selected(unit)) {
+            self.show_first();
+        }
+    }
+
+    // Deselects one unit, the panel moves on to the next one if it was showing it
+    pub fn remove(&mut self, slot: u8) {
+        self.units.retain(|(selected, _)| *selected != slot);
+        if self.unit == Some(slot) {
+            self.show_first();
+        }
+    }
+
+    fn show_first(&mut self) {
+        self.unit = self.units.first().map(|(slot, _)| *slot);
+        self.e = self.units.first().map(|(_, e)| *e);
+    }
+
+    pub fn set(&mut self, mut units: Vec<(u8, Entity)>) {
+        units.sort_by_key(|(slot, _)| *slot);
+        self.units = units;
+        self.show_first();
+    }
+
+    pub fn clear(&mut self) {
+        self.unit = None;
+        self.e = None;
+        self.units.clear();
+    }
+
+    pub fn is_selected(&self, slot: u8) -> bool {
+        self.units.iter().any(|(selected, _)| *selected == slot)
+    }
 }
 
 #[derive(Resource)]
 pub struct MenuOpen;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> impl Iterator<Item = String> {
+        args.iter()
+            .map(|arg| arg.to_string())
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    #[test]
+    fn seed_flag_forms() {
+        assert_eq!(seed_from_args(args(&["--seed", "abc"])), Some("abc".into()));
+        assert_eq!(seed_from_args(args(&["--seed=a b"])), Some("a b".into()));
+        assert_eq!(seed_from_args(args(&["--other"])), None);
+    }
+
+    #[test]
+    fn missing_seed_value_is_none() {
+        assert_eq!(seed_from_args(args(&["--seed"])), None);
+        assert_eq!(seed_from_args(args(&["--seed", "--fullscreen"])), None);
+        assert_eq!(seed_from_args(args(&["--seed="])), None);
+    }
+
+    fn unit(n: u32) -> Entity {
+        Entity::from_raw(n)
+    }
+
+    #[test]
+    fn select_replaces_everything() {
+        let mut selected = SelectedUnit::default();
+        selected.set(vec![(3, unit(3)), (1, unit(1))]);
+        selected.select(2, unit(2));
+
+        assert_eq!(selected.unit, Some(2));
+        assert_eq!(selected.e, Some(unit(2)));
+        assert_eq!(selected.units, vec![(2, unit(2))]);
+        assert!(selected.is_selected(2));
+        assert!(!selected.is_selected(1));
+    }
+
+    #[test]
+    fn set_sorts_and_shows_the_first() {
+        let mut selected = SelectedUnit::default();
+        selected.set(vec![(4, unit(4)), (0, unit(0)), (2, unit(2))]);
+
+        assert_eq!(selected.unit, Some(0));
+        assert_eq!(selected.e, Some(unit(0)));
+        assert_eq!(
+            selected.units,
+            vec![(0, unit(0)), (2, unit(2)), (4, unit(4))]
+        );
+
+        selected.set(vec![]);
+        assert_eq!(selected.unit, None);
+        assert_eq!(selected.e, None);
+    }
+
+    #[test]
+    fn toggle_adds_and_removes() {
+        let mut selected = SelectedUnit::default();
+        selected.select(3, unit(3));
+
+        selected.toggle(1, unit(1));
+        assert_eq!(selected.units, vec![(1, unit(1)), (3, unit(3))]);
+        // Still showing the one that was there first
+        assert_eq!(selected.unit, Some(3));
+
+        selected.toggle(3, unit(3));
+        assert_eq!(selected.units, vec![(1, unit(1))]);
+        assert_eq!(selected.unit, Some(1));
+        assert_eq!(selected.e, Some(unit(1)));
+
+        selected.toggle(1, unit(1));
+        assert!(selected.units.is_empty());
+        assert_eq!(selected.unit, None);
+        assert_eq!(selected.e, None);
+    }
+
+    #[test]
+    fn remove_moves_the_panel_on() {
+        let mut selected = SelectedUnit::default();
+        selected.set(vec![(1, unit(1)), (2, unit(2)), (5, unit(5))]);
+
+        selected.remove(2);
+        assert_eq!(selected.units, vec![(1, unit(1)), (5, unit(5))]);
+        assert_eq!(selected.unit, Some(1));
+
+        selected.remove(1);
+        assert_eq!(selected.unit, Some(5));
+        assert_eq!(selected.e, Some(unit(5)));
+
+        // Not selected, nothing happens
+        selected.remove(3);
+        assert_eq!(selected.units, vec![(5, unit(5))]);
+
+        selected.remove(5);
+        assert_eq!(selected.unit, None);
+        assert_eq!(selected.e, None);
+    }
+
+    #[test]
+    fn clear_drops_everything() {
+        let mut selected = SelectedUnit::default();
+        selected.set(vec![(1, unit(1)), (2, unit(2))]);
+        selected.clear();
+
+        assert_eq!(selected.unit, None);
+        assert_eq!(selected.e, None);
+        assert!(!selected.is_selected(1));
+    }
+}
@@ -0,0 +1,235 @@
+use bevy::prelude::*;
+use bevy_ecs_tilemap::prelude::*;
+
+use crate::*;
+
+// assets/tiles.png, row 0 is the plain tiles (see Biome::tile_index), row 1 the
+// coast transitions and row 2 the mountain transitions
+pub struct Tileset {
+    pub columns: usize,
+    pub rows: usize,
+}
+
+pub const TILESET: Tileset = Tileset {
+    columns: 20,
+    rows: 3,
+};
+
+impl Tileset {
+    // Tile size comes from GameConfig, the map and the atlas have to agree on it
+    pub fn atlas_layout(&self, tile_size: f32) -> TextureAtlasLayout {
+        TextureAtlasLayout::from_grid(Vec2::splat(tile_size), self.columns, self.rows, None, None)
+    }
+}
+
+// Each transition row is 16 edge tiles (indexed by which sides touch the other
+// terrain, so two sides make an outer corner) then the 4 inner corners
+pub const COAST_TILES: u32 = 20;
+pub const MOUNTAIN_TILES: u32 = 40;
+
+const NORTH: u32 = 1;
+const EAST: u32 = 2;
+const SOUTH: u32 = 4;
+const WEST: u32 = 8;
+const INNER_CORNERS: u32 = 16;
+
+struct TransitionRule {
+    // Tiles that get the transition drawn on them
+    base: fn(Biome) -> bool,
+    // Neighbours that count as an edge
+    edge: fn(Biome) -> bool,
+    first_tile: u32,
+}
+
+const RULES: [TransitionRule; 2] = [
+    // Shore drawn on the water side, so land biomes keep their own tiles
+    TransitionRule {
+        base: |biome| biome == Biome::ShallowWater,
+        edge: |biome| !biome.is_water(),
+        first_tile: COAST_TILES,
+    },
+    // Foothills around mountains
+    TransitionRule {
+        base: |biome| biome == Biome::Mountain,
+        edge: |biome| !matches!(biome, Biome::Mountain | Biome::Snow),
+        first_tile: MOUNTAIN_TILES,
+    },
+];
+
+pub fn autotile_index(biomes: &BiomeMap, x: u32, y: u32) -> u32 {
+    let biome = biomes.get(x, y);
+
+    let Some(rule) = RULES.iter().find(|rule| (rule.base)(biome)) else {
+        return biome.tile_index();
+    };
+
+    // Off the map never counts as an edge
+    let (width, height) = biomes.size();
+    let is_edge = |dx: i32, dy: i32| {
+        let (nx, ny) = (x as i32 + dx, y as i32 + dy);
+        nx >= 0
+            && ny >= 0
+            && (nx as usize) < width
+            && (ny as usize) < height
+            && (rule.edge)(biomes.get(nx as u32, ny as u32))
+    };
+
+    let mut mask = 0;
+    for (bit, dx, dy) in [(NORTH, 0, 1), (EAST, 1, 0), (SOUTH, 0, -1), (WEST, -1, 0)] {
+        if is_edge(dx, dy) {
+            mask |= bit;
+        }
+    }
+
+    if mask != 0 {
+        return rule.first_tile + mask;
+    }
+
+    // Only diagonals touch, NE, SE, SW, NW
+    for (corner, (dx, dy)) in [(1, 1), (1, -1), (-1, -1), (-1, 1)].into_iter().enumerate() {
+        if is_edge(dx, dy) {
+            return rule.first_tile + INNER_CORNERS + corner as u32;
+        }
+    }
+
+    biome.tile_index()
+}
+
+// After a tile changes its neighbours may need a different transition, only
+// touches tiles in loaded chunks (the rest pick it up when they spawn)
+pub fn retile_around(
+    map_tiles: &MapTiles,
+    biomes: &BiomeMap,
+    center: &TilePos,
+    tiles: &mut Query<&mut TileTextureIndex>,
+) {
+    for dx in -1..=1 {
+        for dy in -1..=1 {
+            let (x, y) = (center.x as i32 + dx, center.y as i32 + dy);
+            if x < 0 || y < 0 {
+                continue;
+            }
+            let tile_pos = TilePos {
+                x: x as u32,
+                y: y as u32,
+            };
+
+            let Some(tile_entity) = map_tiles.ground(&tile_pos) else {
+                continue;
+            };
+
+            if let Ok(mut texture_index) = tiles.get_mut(tile_entity) {
+                let index = autotile_index(biomes, tile_pos.x, tile_pos.y);
+                if texture_index.0 != index {
+                    texture_index.0 = index;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 5x5 of one biome, the middle tile is the one being checked
+    fn filled(biome: Biome) -> BiomeMap {
+        let mut biomes = BiomeMap::generate(0, &NoiseMap::new(5, 5));
+        for y in 0..5 {
+            for x in 0..5 {
+                biomes.set(x, y, biome);
+            }
+        }
+        biomes
+    }
+
+    #[test]
+    fn plain_tiles_without_neighbours() {
+        let biomes = filled(Biome::Grassland);
+        assert_eq!(autotile_index(&biomes, 2, 2), Biome::Grassland.tile_index());
+
+        let biomes = filled(Biome::ShallowWater);
+        assert_eq!(
+            autotile_index(&biomes, 2, 2),
+            Biome::ShallowWater.tile_index()
+        );
+        // Off the map isn't land
+        assert_eq!(
+            autotile_index(&biomes, 0, 0),
+            Biome::ShallowWater.tile_index()
+        );
+    }
+
+    #[test]
+    fn coast_edges() {
+        let mut biomes = filled(Biome::ShallowWater);
+        biomes.set(2, 3, Biome::Grassland);
+        assert_eq!(autotile_index(&biomes, 2, 2), COAST_TILES + NORTH);
+
+        biomes.set(3, 2, Biome::Beach);
+        assert_eq!(autotile_index(&biomes, 2, 2), COAST_TILES + NORTH + EAST);
+
+        biomes.set(2, 1, Biome::Forest);
+        biomes.set(1, 2, Biome::Mountain);
+        assert_eq!(
+            autotile_index(&biomes, 2, 2),
+            COAST_TILES + NORTH + EAST + SOUTH + WEST
+        );
+
+        // Deep water next to land doesn't get a shore
+        let mut biomes = filled(Biome::DeepWater);
+        biomes.set(2, 3, Biome::Grassland);
+        assert_eq!(autotile_index(&biomes, 2, 2), Biome::DeepWater.tile_index());
+
+        // Nor does shallow water next to deep
+        let mut biomes = filled(Biome::ShallowWater);
+        biomes.set(2, 3, Biome::DeepWater);
+        assert_eq!(
+            autotile_index(&biomes, 2, 2),
+            Biome::ShallowWater.tile_index()
+        );
+    }
+
+    #[test]
+    fn coast_inner_corners() {
+        for (corner, (x, y)) in [(3, 3), (3, 1), (1, 1), (1, 3)].into_iter().enumerate() {
+            let mut biomes = filled(Biome::ShallowWater);
+            biomes.set(x, y, Biome::Grassland);
+            assert_eq!(
+                autotile_index(&biomes, 2, 2),
+                COAST_TILES + INNER_CORNERS + corner as u32
+            );
+        }
+
+        // An edge wins over a corner
+        let mut biomes = filled(Biome::ShallowWater);
+        biomes.set(3, 3, Biome::Grassland);
+        biomes.set(2, 1, Biome::Grassland);
+        assert_eq!(autotile_index(&biomes, 2, 2), COAST_TILES + SOUTH);
+    }
+
+    #[test]
+    fn mountain_edges() {
+        let mut biomes = filled(Biome::Mountain);
+        biomes.set(2, 1, Biome::Grassland);
+        biomes.set(1, 2, Biome::Forest);
+        assert_eq!(autotile_index(&biomes, 2, 2), MOUNTAIN_TILES + SOUTH + WEST);
+
+        // Snow is part of the mountain
+        let mut biomes = filled(Biome::Mountain);
+        biomes.set(2, 3, Biome::Snow);
+        assert_eq!(autotile_index(&biomes, 2, 2), Biome::Mountain.tile_index());
+
+        let mut biomes = filled(Biome::Mountain);
+        biomes.set(1, 3, Biome::ShallowWater);
+        assert_eq!(
+            autotile_index(&biomes, 2, 2),
+            MOUNTAIN_TILES + INNER_CORNERS + 3
+        );
+
+        // The grass doesn't get foothills drawn on it
+        let mut biomes = filled(Biome::Grassland);
+        biomes.set(2, 3, Biome::Mountain);
+        assert_eq!(autotile_index(&biomes, 2, 2), Biome::Grassland.tile_index());
+    }
+}
@@ -0,0 +1,267 @@
+use bevy_ecs_tilemap::prelude::TilePos;
+use noise::utils::{NoiseMap, NoiseMapBuilder, PlaneMapBuilder};
+use noise::{Fbm, Perlin};
+
+use crate::{RiverNetwork, WaterCell};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Biome {
+    DeepWater,
+    ShallowWater,
+    Beach,
+    Grassland,
+    Forest,
+    Mountain,
+    Desert,
+    Swamp,
+    Tundra,
+    Snow,
+    Savanna,
+    Jungle,
+    Taiga,
+}
+
+impl Biome {
+    pub fn tile_index(&self) -> u32 {
+        match self {
+            Biome::DeepWater => 0,
+            Biome::ShallowWater => 1,
+            Biome::Beach => 2,
+            Biome::Grassland => 3,
+            Biome::Forest => 4,
+            Biome::Mountain => 5,
+            Biome::Desert => 13,
+            Biome::Swamp => 14,
+            Biome::Tundra => 15,
+            Biome::Snow => 16,
+            Biome::Savanna => 17,
+            Biome::Jungle => 18,
+            Biome::Taiga => 19,
+        }
+    }
+
+    // Flat colour for the minimap and anything else too small for the tileset
+    pub fn color(&self) -> [u8; 3] {
+        match self {
+            Biome::DeepWater => [16, 42, 110],
+            Biome::ShallowWater => [52, 110, 190],
+            Biome::Beach => [222, 206, 150],
+            Biome::Grassland => [110, 170, 70],
+            Biome::Forest => [40, 105, 45],
+            Biome::Mountain => [130, 125, 120],
+            Biome::Desert => [230, 190, 110],
+            Biome::Swamp => [80, 95, 60],
+            Biome::Tundra => [150, 160, 140],
+            Biome::Snow => [240, 240, 245],
+            Biome::Savanna => [190, 180, 90],
+            Biome::Jungle => [20, 130, 50],
+            Biome::Taiga => [60, 100, 80],
+        }
+    }
+
+    pub fn is_water(&self) -> bool {
+        matches!(self, Biome::DeepWater | Biome::ShallowWater)
+    }
+
+    // Chance a ruin ends up here, old civilisations liked the dry and the high places
+    pub fn treasure_weight(&self) -> f64 {
+        match self {
+            Biome::DeepWater | Biome::ShallowWater => 0.0,
+            Biome::Beach => 0.3,
+            Biome::Grassland => 0.6,
+            Biome::Forest => 0.5,
+            Biome::Mountain => 0.8,
+            Biome::Desert => 1.0,
+            Biome::Swamp => 0.4,
+            Biome::Tundra => 0.4,
+            Biome::Snow => 0.2,
+            Biome::Savanna => 0.7,
+            Biome::Jungle => 0.9,
+            Biome::Taiga => 0.4,
+        }
+    }
+}
+
+impl std::fmt::Display for Biome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Biome::DeepWater => write!(f, "Deep Water"),
+            Biome::ShallowWater => write!(f, "Shallow Water"),
+            Biome::Beach => write!(f, "Beach"),
+            Biome::Grassland => write!(f, "Grassland"),
+            Biome::Forest => write!(f, "Forest"),
+            Biome::Mountain => write!(f, "Mountain"),
+            Biome::Desert => write!(f, "Desert"),
+            Biome::Swamp => write!(f, "Swamp"),
+            Biome::Tundra => write!(f, "Tundra"),
+            Biome::Snow => write!(f, "Snow"),
+            Biome::Savanna => write!(f, "Savanna"),
+            Biome::Jungle => write!(f, "Jungle"),
+            Biome::Taiga => write!(f, "Taiga"),
+        }
+    }
+}
+
+// Rows are temperature (cold, temperate, hot), columns are moisture (dry, medium, wet)
+const BIOME_TABLE: [[Biome; 3]; 3] = [
+    [Biome::Tundra, Biome::Tundra, Biome::Taiga],
+    [Biome::Grassland, Biome::Forest, Biome::Swamp],
+    [Biome::Desert, Biome::Savanna, Biome::Jungle],
+];
+
+// Elevation takes priority (water, beaches and peaks), then the climate table decides
+pub fn classify_biome(elevation: f64, temperature: f64, moisture: f64) -> Biome {
+    let elevation = elevation.abs();
+
+    // Higher ground is colder
+    let temperature = (temperature - (elevation - 0.3).max(0.0) * 0.5).clamp(0.0, 1.0);
+
+    match elevation {
+        v if v < 0.03 => Biome::DeepWater,
+        v if v < 0.08 => Biome::ShallowWater,
+        v if v < 0.1 => Biome::Beach,
+        v if v >= 0.6 => {
+            if temperature < 0.25 {
+                Biome::Snow
+            } else {
+                Biome::Mountain
+            }
+        }
+        _ => {
+            let t = band(temperature);
+            let m = band(moisture);
+            BIOME_TABLE[t][m]
+        }
+    }
+}
+
+fn band(val: f64) -> usize {
+    match val {
+        v if v < 0.33 => 0,
+        v if v < 0.66 => 1,
+        _ => 2,
+    }
+}
+
+pub struct BiomeMap {
+    width: usize,
+    height: usize,
+    biomes: Vec<Biome>,
+    // Kept so tiles can be reclassified when their elevation changes
+    temperature: Vec<f32>,
+    moisture: Vec<f32>,
+}
+
+impl Default for BiomeMap {
+    fn default() -> Self {
+        Self {
+            width: 0,
+            height: 0,
+            biomes: vec![],
+            temperature: vec![],
+            moisture: vec![],
+        }
+    }
+}
+
+impl BiomeMap {
+    // Moisture and temperature get their own noise, offset from the elevation seed
+    pub fn generate(seed: u32, elevation: &NoiseMap) -> Self {
+        let (width, height) = elevation.size();
+
+        let moisture = climate_noise(seed.wrapping_add(1), width, height);
+        let temperature = climate_noise(seed.wrapping_add(2), width, height);
+
+        let mut biomes = Vec::with_capacity(width * height);
+        let mut temperatures = Vec::with_capacity(width * height);
+        let mut moistures = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                biomes.push(classify_biome(
+                    elevation.get_value(x, y),
+                    temperature.get_value(x, y),
+                    moisture.get_value(x, y),
+                ));
+                temperatures.push(temperature.get_value(x, y) as f32);
+                moistures.push(moisture.get_value(x, y) as f32);
+            }
+        }
+
+        Self {
+            width,
+            height,
+            biomes,
+            temperature: temperatures,
+            moisture: moistures,
+        }
+    }
+
+    // Same climate, new elevation
+    pub fn reclassify(&mut self, x: u32, y: u32, elevation: f64) -> Biome {
+        let (ux, uy) = (x as usize, y as usize);
+        if ux >= self.width || uy >= self.height {
+            return Biome::DeepWater;
+        }
+
+        let idx = ux + uy * self.width;
+        let biome = classify_biome(
+            elevation,
+            self.temperature[idx] as f64,
+            self.moisture[idx] as f64,
+        );
+        self.biomes[idx] = biome;
+        biome
+    }
+
+    // Rivers and lakes show up as shallow water
+    pub fn apply_rivers(&mut self, rivers: &RiverNetwork) {
+        for y in 0..self.height as u32 {
+            for x in 0..self.width as u32 {
+                if rivers.get(x, y) != WaterCell::Dry {
+                    self.set(x, y, Biome::ShallowWater);
+                }
+            }
+        }
+    }
+
+    pub fn get(&self, x: u32, y: u32) -> Biome {
+        let (x, y) = (x as usize, y as usize);
+        if x < self.width && y < self.height {
+            self.biomes[x + y * self.width]
+        } else {
+            Biome::DeepWater
+        }
+    }
+
+    pub fn get_tile(&self, tile_pos: &TilePos) -> Biome {
+        self.get(tile_pos.x, tile_pos.y)
+    }
+
+    pub fn set(&mut self, x: u32, y: u32, biome: Biome) {
+        let (x, y) = (x as usize, y as usize);
+        if x < self.width && y < self.height {
+            self.biomes[x + y * self.width] = biome;
+        }
+    }
+
+    pub fn size(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+}
+
+// Perlin fbm, rescaled from -1..1 to 0..1
+fn climate_noise(seed: u32, width: usize, height: usize) -> NoiseMap {
+    let fbm = Fbm::<Perlin>::new(seed);
+
+    let mut noise_map = PlaneMapBuilder::new(fbm)
+        .set_size(width, height)
+        .set_x_bounds(-2.0, 2.0)
+        .set_y_bounds(-2.0, 2.0)
+        .build();
+
+    for val in noise_map.iter_mut() {
+        *val = ((*val + 1.0) / 2.0).clamp(0.0, 1.0);
+    }
+
+    noise_map
+}
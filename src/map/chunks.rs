@@ -0,0 +1,319 @@
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use bevy::window::PrimaryWindow;
+use bevy_ecs_tilemap::prelude::*;
+
+use crate::*;
+
+// Ground, fog of war and the territory overlay are split into CHUNK_SIZE x
+// CHUNK_SIZE tilemaps that only exist while they're near the camera
+pub const CHUNK_SIZE: u32 = 64;
+
+#[derive(Component)]
+pub struct MapChunk {
+    pub pos: UVec2,
+}
+
+#[derive(Resource)]
+pub struct MapChunks {
+    pub map_size: TilemapSize,
+    pub tile_size: TilemapTileSize,
+    pub grid_size: TilemapGridSize,
+    pub map_type: TilemapType,
+    // Where the whole map would be if it was one tilemap, chunks are offset from here
+    pub origin: Transform,
+    ground: HashMap<UVec2, Entity>,
+    fog: HashMap<UVec2, Entity>,
+    overlay: HashMap<UVec2, Entity>,
+}
+
+impl MapChunks {
+    pub fn new(map_size: TilemapSize, tile_size: TilemapTileSize) -> Self {
+        let grid_size = tile_size.into();
+        let map_type = TilemapType::default();
+
+        Self {
+            map_size,
+            tile_size,
+            grid_size,
+            map_type,
+            origin: get_tilemap_center_transform(&map_size, &grid_size, &map_type, 0.0),
+            ground: HashMap::new(),
+            fog: HashMap::new(),
+            overlay: HashMap::new(),
+        }
+    }
+
+    pub fn chunk_count(&self) -> UVec2 {
+        UVec2::new(
+            (self.map_size.x + CHUNK_SIZE - 1) / CHUNK_SIZE,
+            (self.map_size.y + CHUNK_SIZE - 1) / CHUNK_SIZE,
+        )
+    }
+
+    // Edge chunks can be smaller when the map isn't a multiple of CHUNK_SIZE
+    pub fn chunk_size(&self, chunk: UVec2) -> TilemapSize {
+        TilemapSize {
+            x: CHUNK_SIZE.min(self.map_size.x - chunk.x * CHUNK_SIZE),
+            y: CHUNK_SIZE.min(self.map_size.y - chunk.y * CHUNK_SIZE),
+        }
+    }
+
+    // Global tile position to (chunk, position within the chunk)
+    pub fn chunk_of(&self, tile_pos: &TilePos) -> Option<(UVec2, TilePos)> {
+        if tile_pos.x >= self.map_size.x || tile_pos.y >= self.map_size.y {
+            return None;
+        }
+
+        Some((
+            UVec2::new(tile_pos.x / CHUNK_SIZE, tile_pos.y / CHUNK_SIZE),
+            TilePos {
+                x: tile_pos.x % CHUNK_SIZE,
+                y: tile_pos.y % CHUNK_SIZE,
+            },
+        ))
+    }
+
+    pub fn chunk_transform(&self, chunk: UVec2, z: f32) -> Transform {
+        let offset = Vec3::new(
+            (chunk.x * CHUNK_SIZE) as f32 * self.grid_size.x,
+            (chunk.y * CHUNK_SIZE) as f32 * self.grid_size.y,
+            z,
+        );
+        Transform::from_translation(self.origin.translation + offset)
+    }
+
+    // Unclamped, so it can be off the map
+    pub fn world_to_chunk(&self, world_pos: Vec2) -> IVec2 {
+        let grid_size = Vec2::new(self.grid_size.x, self.grid_size.y);
+        let local = world_pos - self.origin.translation.xy() + grid_size / 2.0;
+        let chunk_world_size = grid_size * CHUNK_SIZE as f32;
+        (local / chunk_world_size).floor().as_ivec2()
+    }
+
+    pub fn world_to_tile(&self, world_pos: Vec2) -> Option<TilePos> {
+        let pos_in_map = {
+            // Extend the world pos vec3 by 0.0 and 1.0
+            let world_pos = Vec4::from((world_pos, 0.0, 1.0));
+            let pos_in_map = self.origin.compute_matrix().inverse() * world_pos;
+            pos_in_map.xy()
+        };
+        TilePos::from_world_pos(&pos_in_map, &self.map_size, &self.grid_size, &self.map_type)
+    }
+
+    pub fn tile_to_world(&self, tile_pos: &TilePos) -> Vec2 {
+        self.origin.translation.xy() + tile_pos.center_in_world(&self.grid_size, &self.map_type)
+    }
+
+    pub fn is_loaded(&self, chunk: UVec2) -> bool {
+        self.ground.contains_key(&chunk)
+    }
+
+    // Forget every overlay chunk so stream_chunks rebuilds them, caller despawns
+    pub fn take_overlay(&mut self) -> Vec<Entity> {
+        self.overlay.drain().map(|(_, entity)| entity).collect()
+    }
+}
+
+// Look up tile entities by their global TilePos, wherever their chunk is
+#[derive(SystemParam)]
+pub struct MapTiles<'w, 's> {
+    chunks: Res<'w, MapChunks>,
+    storages: Query<'w, 's, &'static TileStorage, (With<MapChunk>, Without<MapStuff>)>,
+}
+
+impl<'w, 's> MapTiles<'w, 's> {
+    pub fn ground(&self, tile_pos: &TilePos) -> Option<Entity> {
+        self.lookup(&self.chunks.ground, tile_pos)
+    }
+
+    pub fn fog(&self, tile_pos: &TilePos) -> Option<Entity> {
+        self.lookup(&self.chunks.fog, tile_pos)
+    }
+
+    pub fn chunks(&self) -> &MapChunks {
+        &self.chunks
+    }
+
+    fn lookup(&self, layer: &HashMap<UVec2, Entity>, tile_pos: &TilePos) -> Option<Entity> {
+        let (chunk, local) = self.chunks.chunk_of(tile_pos)?;
+        let chunk_entity = layer.get(&chunk)?;
+        self.storages.get(*chunk_entity).ok()?.get(&local)
+    }
+}
+
+pub fn stream_chunks(
+    mut commands: Commands,
+    mut chunks: ResMut<MapChunks>,
+    camera_q: Query<(&Transform, &OrthographicProjection), With<Camera>>,
+    window_q: Query<&Window, With<PrimaryWindow>>,
+    storage_q: Query<&TileStorage, (With<MapChunk>, Without<MapStuff>)>,
+    state: Res<GameState>,
+    assets: Res<GameAssets>,
+    fog: Res<FogOfWar>,
+    territories: Res<Territories>,
+    show_territories: Res<ShowTerritories>,
+) {
+    let Ok((camera_transform, projection)) = camera_q.get_single() else {
+        return;
+    };
+    let Ok(window) = window_q.get_single() else {
+        return;
+    };
+
+    let half_extents = Vec2::new(window.width(), window.height()) / 2.0
+        * camera_transform.scale.xy()
+        * projection.scale;
+    let camera_pos = camera_transform.translation.xy();
+
+    // One extra ring of chunks so panning doesn't show the edge
+    let last_chunk = chunks.chunk_count().as_ivec2() - IVec2::ONE;
+    let min = (chunks.world_to_chunk(camera_pos - half_extents) - IVec2::ONE).max(IVec2::ZERO);
+    let max = (chunks.world_to_chunk(camera_pos + half_extents) + IVec2::ONE).min(last_chunk);
+
+    for x in min.x..=max.x {
+        for y in min.y..=max.y {
+            let chunk = UVec2::new(x as u32, y as u32);
+            if !chunks.is_loaded(chunk) {
+                spawn_chunk(&mut commands, &mut chunks, chunk, &state, &assets, &fog);
+            }
+            // Also dropped on its own whenever the territories change
+            if !chunks.overlay.contains_key(&chunk) {
+                let overlay = spawn_territory_chunk(
+                    &mut commands,
+                    &chunks,
+                    chunk,
+                    &territories,
+                    &assets,
+                    show_territories.0,
+                );
+                chunks.overlay.insert(chunk, overlay);
+            }
+        }
+    }
+
+    // And another ring of slack before despawning, so chunks on the border don't flicker
+    let keep_min = min - IVec2::ONE;
+    let keep_max = max + IVec2::ONE;
+    let stale: Vec<UVec2> = chunks
+        .ground
+        .keys()
+        .filter(|chunk| {
+            let chunk = chunk.as_ivec2();
+            chunk.x < keep_min.x
+                || chunk.y < keep_min.y
+                || chunk.x > keep_max.x
+                || chunk.y > keep_max.y
+        })
+        .copied()
+        .collect();
+
+    for chunk in stale {
+        let ground = chunks.ground.remove(&chunk);
+        let fog = chunks.fog.remove(&chunk);
+        let overlay = chunks.overlay.remove(&chunk);
+        for tilemap_entity in ground.into_iter().chain(fog).chain(overlay) {
+            despawn_chunk(&mut commands, &storage_q, tilemap_entity);
+        }
+    }
+}
+
+fn spawn_chunk(
+    commands: &mut Commands,
+    chunks: &mut MapChunks,
+    chunk: UVec2,
+    state: &GameState,
+    assets: &GameAssets,
+    fog: &FogOfWar,
+) {
+    let size = chunks.chunk_size(chunk);
+    let offset = chunk * CHUNK_SIZE;
+
+    let ground_entity = commands.spawn((MapGround, MapChunk { pos: chunk })).id();
+    let mut ground_storage = TileStorage::empty(size);
+
+    let fog_entity = commands.spawn((MapFogOfWar, MapChunk { pos: chunk })).id();
+    let mut fog_storage = TileStorage::empty(size);
+
+    for x in 0..size.x {
+        for y in 0..size.y {
+            let tile_pos = TilePos { x, y };
+            let (global_x, global_y) = (offset.x + x, offset.y + y);
+
+            let tile_entity = commands
+                .spawn(TileBundle {
+                    position: tile_pos,
+                    tilemap_id: TilemapId(ground_entity),
+                    texture_index: TileTextureIndex(autotile_index(
+                        &state.biomes,
+                        global_x,
+                        global_y,
+                    )),
+                    ..Default::default()
+                })
+                .id();
+            ground_storage.set(&tile_pos, tile_entity);
+
+            // Fog of war all black (tilemap index 7)
+            let (visible, color) = fog.state(global_x, global_y).tile_visuals();
+            let tile_entity = commands
+                .spawn(TileBundle {
+                    position: tile_pos,
+                    tilemap_id: TilemapId(fog_entity),
+                    texture_index: TileTextureIndex(7),
+                    // Turn off fog of war by making this always TileVisible(false)! easy peasy
+                    visible,
+                    color,
+                    ..Default::default()
+                })
+                .id();
+            fog_storage.set(&tile_pos, tile_entity);
+        }
+    }
+
+    commands.entity(ground_entity).insert((
+        TilemapBundle {
+            grid_size: chunks.grid_size,
+            map_type: chunks.map_type,
+            size,
+            storage: ground_storage,
+            texture: TilemapTexture::Single(assets.tiles.clone()),
+            tile_size: chunks.tile_size,
+            transform: chunks.chunk_transform(chunk, 0.0),
+            ..Default::default()
+        },
+        Name::from(format!("GroundChunk {} {}", chunk.x, chunk.y)),
+    ));
+
+    commands.entity(fog_entity).insert((
+        TilemapBundle {
+            grid_size: chunks.grid_size,
+            map_type: chunks.map_type,
+            size,
+            storage: fog_storage,
+            texture: TilemapTexture::Single(assets.tiles.clone()),
+            tile_size: chunks.tile_size,
+            transform: chunks.chunk_transform(chunk, 2.0),
+            ..Default::default()
+        },
+        Name::from(format!("FogChunk {} {}", chunk.x, chunk.y)),
+    ));
+
+    chunks.ground.insert(chunk, ground_entity);
+    chunks.fog.insert(chunk, fog_entity);
+}
+
+// Tiles aren't children of their tilemap, so clean them up by hand
+pub fn despawn_chunk(
+    commands: &mut Commands,
+    storage_q: &Query<&TileStorage, (With<MapChunk>, Without<MapStuff>)>,
+    tilemap_entity: Entity,
+) {
+    if let Ok(storage) = storage_q.get(tilemap_entity) {
+        for tile_entity in storage.iter().flatten() {
+            commands.entity(*tile_entity).despawn();
+        }
+    }
+    commands.entity(tilemap_entity).despawn_recursive();
+}
@@ -0,0 +1,294 @@
+use std::collections::HashSet;
+
+use bevy::prelude::*;
+use noise::utils::NoiseMap;
+use rand::Rng;
+use serde::Serialize;
+
+use crate::*;
+
+// Tries per deposit before giving up on filling the preset's count
+const DEPOSIT_ATTEMPTS_PER_SPOT: usize = 20;
+
+// Crystals grow back this much per second, ore veins run dry
+pub const CRYSTAL_REGROWTH: f32 = 0.5;
+
+// Marker tints over the blank tile (index 11)
+pub const DEPOSIT_TILE: u32 = 11;
+const ORE_COLOR: Color = Color::rgb(0.72, 0.45, 0.2);
+const CRYSTAL_COLOR: Color = Color::rgb(0.65, 0.3, 0.95);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum DepositKind {
+    Ore,
+    Crystal,
+}
+
+impl DepositKind {
+    // Ore is in the high ground, crystals like the cold and the wet
+    pub fn weight(&self, biome: Biome, elevation: f64) -> f64 {
+        match self {
+            DepositKind::Ore => match biome {
+                Biome::Mountain => 1.0,
+                Biome::Snow => 0.5,
+                Biome::Desert => 0.3,
+                // Hills
+                _ => ((elevation - 0.35) * 2.0).clamp(0.02, 0.4),
+            },
+            DepositKind::Crystal => match biome {
+                Biome::Snow => 0.6,
+                Biome::Jungle | Biome::Swamp => 0.5,
+                Biome::Tundra | Biome::Taiga => 0.4,
+                Biome::Forest => 0.2,
+                Biome::Mountain => 0.15,
+                _ => 0.02,
+            },
+        }
+    }
+
+    pub fn renews(&self) -> bool {
+        matches!(self, DepositKind::Crystal)
+    }
+
+    pub fn color(&self) -> Color {
+        match self {
+            DepositKind::Ore => ORE_COLOR,
+            DepositKind::Crystal => CRYSTAL_COLOR,
+        }
+    }
+}
+
+impl std::fmt::Display for DepositKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DepositKind::Ore => write!(f, "Ore"),
+            DepositKind::Crystal => write!(f, "Crystal"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Deposit {
+    pub location: (u32, u32),
+    pub kind: DepositKind,
+    pub amount: f32,
+    // Crystals grow back up to this
+    pub capacity: f32,
+}
+
+impl Deposit {
+    // Takes up to `wanted`, returns how much was actually there
+    pub fn mine(&mut self, wanted: f32) -> f32 {
+        let mined = wanted.min(self.amount).max(0.0);
+        self.amount -= mined;
+        mined
+    }
+
+    pub fn regrow(&mut self, seconds: f32) {
+        if self.kind.renews() {
+            self.amount = (self.amount + CRYSTAL_REGROWTH * seconds).min(self.capacity);
+        }
+    }
+
+    // Empty crystals still count, they'll grow back
+    pub fn is_exhausted(&self) -> bool {
+        !self.kind.renews() && self.amount <= 0.0
+    }
+}
+
+// Separate from TreasureLocs, treasure is dug up once, deposits are mined over time
+#[derive(Resource, Debug, Clone, Default)]
+pub struct Deposits {
+    pub deposits: Vec<Deposit>,
+}
+
+impl Deposits {
+    pub fn at(&self, loc: (u32, u32)) -> Option<&Deposit> {
+        self.deposits.iter().find(|deposit| deposit.location == loc)
+    }
+
+    pub fn at_mut(&mut self, loc: (u32, u32)) -> Option<&mut Deposit> {
+        self.deposits
+            .iter_mut()
+            .find(|deposit| deposit.location == loc)
+    }
+
+    pub fn remove_exhausted(&mut self) -> Vec<(u32, u32)> {
+        let exhausted = self
+            .deposits
+            .iter()
+            .filter(|deposit| deposit.is_exhausted())
+            .map(|deposit| deposit.location)
+            .collect();
+        self.deposits.retain(|deposit| !deposit.is_exhausted());
+        exhausted
+    }
+}
+
+// Placed by biome and elevation, never in water or on a tower or treasure spot
+pub fn create_deposits<R: Rng + ?Sized>(
+    rng: &mut R,
+    map: &NoiseMap,
+    biomes: &BiomeMap,
+    taken: &HashSet<(u32, u32)>,
+    preset: &WorldGenPreset,
+) -> Vec<Deposit> {
+    let (width, height) = biomes.size();
+    let (width, height) = (width as u32, height as u32);
+
+    let mut taken = taken.clone();
+    let mut deposits = Vec::new();
+
+    let num_deposits = rng.gen_range(preset_range(preset.deposit_spots)) as usize;
+
+    // Bounded so a map that's nearly all water can't spin forever
+    let max_attempts = num_deposits * DEPOSIT_ATTEMPTS_PER_SPOT;
+    let mut attempts = 0;
+
+    while deposits.len() < num_deposits && attempts < max_attempts {
+        attempts += 1;
+
+        let loc = (rng.gen_range(0..width), rng.gen_range(0..height));
+        let biome = biomes.get(loc.0, loc.1);
+        if biome.is_water() || taken.contains(&loc) {
+            continue;
+        }
+
+        let elevation = map.get_value(loc.0 as usize, loc.1 as usize);
+        let ore = DepositKind::Ore.weight(biome, elevation);
+        let crystal = DepositKind::Crystal.weight(biome, elevation);
+
+        // Whichever fits the tile better is more likely
+        if !rng.gen_bool(ore.max(crystal).clamp(0.0, 1.0)) {
+            continue;
+        }
+        let kind = if rng.gen_bool(ore / (ore + crystal)) {
+            DepositKind::Ore
+        } else {
+            DepositKind::Crystal
+        };
+
+        let amount = rng.gen_range(preset_range(preset.deposit_amount)) as f32;
+
+        taken.insert(loc);
+        deposits.push(Deposit {
+            location: loc,
+            kind,
+            amount,
+            capacity: amount,
+        });
+    }
+
+    if deposits.len() < num_deposits {
+        log::warn!(
+            "Only placed {} of {} deposits",
+            deposits.len(),
+            num_deposits
+        );
+    }
+
+    deposits
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::map::fixtures::*;
+
+    fn deposit(kind: DepositKind, amount: f32) -> Deposit {
+        Deposit {
+            location: (0, 0),
+            kind,
+            amount,
+            capacity: amount,
+        }
+    }
+
+    #[test]
+    fn only_on_free_land() {
+        // Sea down the left, a tower and a cluster of treasure spots to keep off
+        let mut state = flat_state(32, 0.6);
+        for y in 0..32 {
+            for x in 0..8 {
+                state.biomes.set(x, y, Biome::DeepWater);
+            }
+        }
+        let mut taken: HashSet<(u32, u32)> = HashSet::new();
+        taken.insert((20, 20));
+        for x in 10..16 {
+            for y in 10..16 {
+                taken.insert((x, y));
+            }
+        }
+
+        let preset = WorldGenPreset {
+            deposit_spots: (300, 301),
+            ..Default::default()
+        };
+        let mut rng = StdRng::seed_from_u64(442);
+        let deposits = create_deposits(&mut rng, &state.map, &state.biomes, &taken, &preset);
+
+        assert!(!deposits.is_empty());
+        let mut seen = HashSet::new();
+        for deposit in deposits.iter() {
+            let (x, y) = deposit.location;
+            assert!(!state.biomes.get(x, y).is_water(), "{:?}", deposit);
+            assert!(!taken.contains(&deposit.location), "{:?}", deposit);
+            assert!(seen.insert(deposit.location), "{:?}", deposit);
+            assert_eq!(deposit.amount, deposit.capacity);
+        }
+    }
+
+    #[test]
+    fn mining_stops_at_what_is_left() {
+        let mut ore = deposit(DepositKind::Ore, 5.0);
+        assert_eq!(ore.mine(3.0), 3.0);
+        assert_eq!(ore.mine(3.0), 2.0);
+        assert_eq!(ore.mine(3.0), 0.0);
+        assert_eq!(ore.amount, 0.0);
+        assert!(ore.is_exhausted());
+
+        // Nothing to give back either
+        let mut ore = deposit(DepositKind::Ore, 5.0);
+        assert_eq!(ore.mine(-1.0), 0.0);
+        assert_eq!(ore.amount, 5.0);
+    }
+
+    #[test]
+    fn only_crystals_regrow_and_never_past_capacity() {
+        let mut crystal = deposit(DepositKind::Crystal, 10.0);
+        crystal.mine(10.0);
+        assert!(!crystal.is_exhausted());
+
+        crystal.regrow(2.0);
+        assert_eq!(crystal.amount, 2.0 * CRYSTAL_REGROWTH);
+        crystal.regrow(1000.0);
+        assert_eq!(crystal.amount, crystal.capacity);
+
+        let mut ore = deposit(DepositKind::Ore, 10.0);
+        ore.mine(10.0);
+        ore.regrow(1000.0);
+        assert_eq!(ore.amount, 0.0);
+    }
+
+    #[test]
+    fn only_dry_ore_is_removed() {
+        let mut deposits = Deposits {
+            deposits: vec![
+                deposit(DepositKind::Ore, 0.0),
+                deposit(DepositKind::Crystal, 0.0),
+                deposit(DepositKind::Ore, 1.0),
+            ],
+        };
+        deposits.deposits[1].location = (1, 0);
+        deposits.deposits[2].location = (2, 0);
+
+        assert_eq!(deposits.remove_exhausted(), vec![(0, 0)]);
+        assert_eq!(deposits.deposits.len(), 2);
+        assert!(deposits.at((0, 0)).is_none());
+        assert!(deposits.at((1, 0)).is_some());
+    }
+}
@@ -0,0 +1,37 @@
+use noise::utils::NoiseMap;
+
+use crate::*;
+
+// Flat worlds for tests, nothing on them until a test puts it there
+
+// Passable for every unit type, and cheap
+pub const LOWLAND: f64 = 0.2;
+
+pub fn flat_map(size: usize, elevation: f64) -> NoiseMap {
+    let mut map = NoiseMap::new(size, size);
+    for val in map.iter_mut() {
+        *val = elevation;
+    }
+    map
+}
+
+// Biomes to match and a river network the size of the map, all of it dry
+pub fn flat_state(size: usize, elevation: f64) -> GameState {
+    let (map, mut rivers) =
+        simulate_rainfall_river_generation_erosion(flat_map(size, elevation), 0, 0.0);
+    let biomes = BiomeMap::generate(0, &map);
+
+    // Flat ground still collects some flow
+    for y in 0..size as u32 {
+        for x in 0..size as u32 {
+            rivers.set(x, y, WaterCell::Dry);
+        }
+    }
+
+    GameState {
+        map,
+        biomes,
+        rivers,
+        ..Default::default()
+    }
+}
@@ -0,0 +1,495 @@
+use bevy::prelude::*;
+use bevy_ecs_tilemap::prelude::*;
+use noise::utils::NoiseMap;
+
+use crate::*;
+
+// How far the player tower can see
+pub const TOWER_VISION_RADIUS: i32 = 20;
+
+// Extra tiles of vision when standing on a mountain
+pub const MOUNTAIN_VISION_BONUS: i32 = 5;
+
+// Terrain this much higher than the viewer blocks line of sight, so small hills don't
+const LINE_OF_SIGHT_MARGIN: f64 = 0.15;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FogState {
+    Unexplored,
+    // Seen before, but nothing is looking at it right now
+    Explored,
+    Visible,
+}
+
+impl FogState {
+    // Fog tiles are solid black (tilemap index 7), explored tiles get a see through version
+    pub fn tile_visuals(&self) -> (TileVisible, TileColor) {
+        match self {
+            FogState::Unexplored => (TileVisible(true), TileColor(Color::WHITE)),
+            FogState::Explored => (
+                TileVisible(true),
+                TileColor(Color::rgba(1.0, 1.0, 1.0, 0.5)),
+            ),
+            FogState::Visible => (TileVisible(false), TileColor(Color::WHITE)),
+        }
+    }
+}
+
+// One bit per tile for each of explored / visible. Visibility is rebuilt every
+// tick, anything that changed since the last tick is flagged dirty for rendering.
+#[derive(Resource, Default)]
+pub struct FogOfWar {
+    width: u32,
+    height: u32,
+    explored: Vec<u64>,
+    visible: Vec<u64>,
+    previous_visible: Vec<u64>,
+    dirty: Vec<u64>,
+}
+
+impl FogOfWar {
+    pub fn new(width: u32, height: u32) -> Self {
+        let words = ((width * height) as usize + 63) / 64;
+        Self {
+            width,
+            height,
+            explored: vec![0; words],
+            visible: vec![0; words],
+            previous_visible: vec![0; words],
+            dirty: vec![0; words],
+        }
+    }
+
+    fn bit(&self, x: u32, y: u32) -> Option<(usize, u64)> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        let idx = (x + y * self.width) as usize;
+        Some((idx / 64, 1 << (idx % 64)))
+    }
+
+    pub fn state(&self, x: u32, y: u32) -> FogState {
+        match self.bit(x, y) {
+            Some((word, mask)) if self.visible[word] & mask != 0 => FogState::Visible,
+            Some((word, mask)) if self.explored[word] & mask != 0 => FogState::Explored,
+            _ => FogState::Unexplored,
+        }
+    }
+
+    pub fn is_visible(&self, x: u32, y: u32) -> bool {
+        self.state(x, y) == FogState::Visible
+    }
+
+    pub fn is_explored(&self, x: u32, y: u32) -> bool {
+        self.state(x, y) != FogState::Unexplored
+    }
+
+    pub fn mark_visible(&mut self, x: u32, y: u32) {
+        if let Some((word, mask)) = self.bit(x, y) {
+            self.visible[word] |= mask;
+            self.explored[word] |= mask;
+        }
+    }
+
+    // Forget what was visible last tick, ready to be rebuilt
+    pub fn begin_update(&mut self) {
+        std::mem::swap(&mut self.visible, &mut self.previous_visible);
+        self.visible.fill(0);
+    }
+
+    pub fn finish_update(&mut self) {
+        for ((dirty, visible), previous) in self
+            .dirty
+            .iter_mut()
+            .zip(self.visible.iter())
+            .zip(self.previous_visible.iter())
+        {
+            *dirty |= visible ^ previous;
+        }
+    }
+
+    // Tiles whose state changed since the last call
+    pub fn drain_dirty(&mut self) -> Vec<TilePos> {
+        let mut changed = vec![];
+        for (word, bits) in self.dirty.iter_mut().enumerate() {
+            let mut remaining = *bits;
+            while remaining != 0 {
+                let bit = remaining.trailing_zeros() as usize;
+                remaining &= remaining - 1;
+
+                let idx = (word * 64 + bit) as u32;
+                changed.push(TilePos {
+                    x: idx % self.width,
+                    y: idx / self.width,
+                });
+            }
+            *bits = 0;
+        }
+        changed
+    }
+}
+
+// Circular vision, blocked by anything enough higher than where the viewer stands.
+// Standing on a mountain sees further.
+pub fn reveal_line_of_sight(fog: &mut FogOfWar, state: &GameState, center: &TilePos, radius: i32) {
+    let (width, height) = state.map.size();
+    if center.x as usize >= width || center.y as usize >= height {
+        return;
+    }
+
+    let viewer_height = state.map.get_value(center.x as usize, center.y as usize);
+    let radius = if matches!(state.biomes.get_tile(center), Biome::Mountain | Biome::Snow) {
+        radius + MOUNTAIN_VISION_BONUS
+    } else {
+        radius
+    };
+
+    fog.mark_visible(center.x, center.y);
+
+    let viewer = Viewer {
+        x: center.x as i32,
+        y: center.y as i32,
+        height: viewer_height,
+        radius,
+    };
+    for octant in OCTANTS.iter() {
+        cast_light(fog, &state.map, &viewer, octant, 1, 1.0, 0.0);
+    }
+}
+
+// Multipliers taking octant 0 to the other 7 (xx, xy, yx, yy)
+const OCTANTS: [(i32, i32, i32, i32); 8] = [
+    (1, 0, 0, 1),
+    (0, 1, 1, 0),
+    (0, -1, 1, 0),
+    (-1, 0, 0, 1),
+    (-1, 0, 0, -1),
+    (0, -1, -1, 0),
+    (0, 1, -1, 0),
+    (1, 0, 0, -1),
+];
+
+struct Viewer {
+    x: i32,
+    y: i32,
+    height: f64,
+    radius: i32,
+}
+
+// Recursive shadowcasting, one octant at a time. Slopes go from 1.0 (the diagonal)
+// down to 0.0 (straight out), anything in the shadow of a blocker is skipped.
+fn cast_light(
+    fog: &mut FogOfWar,
+    map: &NoiseMap,
+    viewer: &Viewer,
+    &(xx, xy, yx, yy): &(i32, i32, i32, i32),
+    row: i32,
+    mut start: f32,
+    end: f32,
+) {
+    if start < end {
+        return;
+    }
+
+    let (width, height) = map.size();
+    let radius_sq = viewer.radius * viewer.radius;
+    let mut new_start = 0.0;
+
+    for distance in row..=viewer.radius {
+        let dy = -distance;
+        let mut blocked = false;
+
+        for dx in -distance..=0 {
+            let left_slope = (dx as f32 - 0.5) / (dy as f32 + 0.5);
+            let right_slope = (dx as f32 + 0.5) / (dy as f32 - 0.5);
+            if start < right_slope {
+                continue;
+            }
+            if end > left_slope {
+                break;
+            }
+
+            let x = viewer.x + dx * xx + dy * xy;
+            let y = viewer.y + dx * yx + dy * yy;
+            let on_map = x >= 0 && y >= 0 && (x as usize) < width && (y as usize) < height;
+
+            // Blockers are seen themselves, just not past
+            if on_map && dx * dx + dy * dy <= radius_sq {
+                fog.mark_visible(x as u32, y as u32);
+            }
+
+            let blocks = !on_map
+                || map.get_value(x as usize, y as usize) > viewer.height + LINE_OF_SIGHT_MARGIN;
+
+            if blocked {
+                if blocks {
+                    new_start = right_slope;
+                } else {
+                    blocked = false;
+                    start = new_start;
+                }
+            } else if blocks && distance < viewer.radius {
+                blocked = true;
+                cast_light(
+                    fog,
+                    map,
+                    viewer,
+                    &(xx, xy, yx, yy),
+                    distance + 1,
+                    start,
+                    left_slope,
+                );
+                new_start = right_slope;
+            }
+        }
+
+        if blocked {
+            break;
+        }
+    }
+}
+
+pub struct FogOfWarPlugin<S: States> {
+    pub state: S,
+}
+
+impl<S: States> Plugin for FogOfWarPlugin<S> {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (update_fog_of_war, render_fog_of_war, hide_fogged_enemies)
+                .chain()
+                .run_if(in_state(self.state.clone())),
+        );
+    }
+}
+
+fn update_fog_of_war(
+    mut fog: ResMut<FogOfWar>,
+    state: Res<GameState>,
+    units: Query<(&TilePos, &Unit), Without<Enemy>>,
+) {
+    fog.begin_update();
+
+    let (tower_x, tower_y) = state.player_tower_location;
+    reveal_line_of_sight(
+        &mut fog,
+        &state,
+        &TilePos {
+            x: tower_x,
+            y: tower_y,
+        },
+        TOWER_VISION_RADIUS,
+    );
+
+    for (tile_pos, unit) in units.iter() {
+        reveal_line_of_sight(&mut fog, &state, tile_pos, unit.visibility as i32);
+    }
+
+    fog.finish_update();
+}
+
+fn render_fog_of_war(
+    mut fog: ResMut<FogOfWar>,
+    map_tiles: MapTiles,
+    mut tile_query: Query<(&mut TileVisible, &mut TileColor)>,
+) {
+    for tile_pos in fog.drain_dirty() {
+        // Chunk might not be spawned, it'll pick the state up when it is
+        let Some(tile_entity) = map_tiles.fog(&tile_pos) else {
+            continue;
+        };
+
+        if let Ok((mut visible, mut color)) = tile_query.get_mut(tile_entity) {
+            (*visible, *color) = fog.state(tile_pos.x, tile_pos.y).tile_visuals();
+        }
+    }
+}
+
+fn hide_fogged_enemies(
+    fog: Res<FogOfWar>,
+    mut enemies: Query<(&TilePos, &mut Visibility), With<Enemy>>,
+) {
+    for (tile_pos, mut visibility) in enemies.iter_mut() {
+        let new_visibility = if fog.is_visible(tile_pos.x, tile_pos.y) {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+
+        if *visibility != new_visibility {
+            *visibility = new_visibility;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+    use crate::map::fixtures::*;
+
+    const SIZE: u32 = 41;
+
+    fn seen_from(state: &GameState, x: u32, y: u32, radius: i32) -> FogOfWar {
+        let mut fog = FogOfWar::new(SIZE, SIZE);
+        reveal_line_of_sight(&mut fog, state, &TilePos { x, y }, radius);
+        fog
+    }
+
+    fn tick(fog: &mut FogOfWar, visible: &[(u32, u32)]) {
+        fog.begin_update();
+        for (x, y) in visible {
+            fog.mark_visible(*x, *y);
+        }
+        fog.finish_update();
+    }
+
+    #[test]
+    fn tiles_start_unexplored() {
+        let fog = FogOfWar::new(100, 70);
+        assert_eq!(fog.state(0, 0), FogState::Unexplored);
+        assert_eq!(fog.state(99, 69), FogState::Unexplored);
+        assert!(!fog.is_explored(50, 50));
+    }
+
+    #[test]
+    fn seen_tiles_stay_explored() {
+        let mut fog = FogOfWar::new(100, 70);
+
+        tick(&mut fog, &[(10, 20)]);
+        assert_eq!(fog.state(10, 20), FogState::Visible);
+        assert_eq!(fog.state(11, 20), FogState::Unexplored);
+
+        tick(&mut fog, &[]);
+        assert_eq!(fog.state(10, 20), FogState::Explored);
+        assert!(fog.is_explored(10, 20));
+        assert!(!fog.is_visible(10, 20));
+
+        tick(&mut fog, &[(10, 20)]);
+        assert_eq!(fog.state(10, 20), FogState::Visible);
+    }
+
+    #[test]
+    fn off_the_map_is_ignored() {
+        let mut fog = FogOfWar::new(100, 70);
+        tick(&mut fog, &[(100, 0), (0, 70)]);
+        assert_eq!(fog.state(100, 0), FogState::Unexplored);
+        assert_eq!(fog.state(0, 1), FogState::Unexplored);
+        assert!(fog.drain_dirty().is_empty());
+    }
+
+    #[test]
+    fn only_changes_are_dirty() {
+        let mut fog = FogOfWar::new(100, 70);
+
+        tick(&mut fog, &[(10, 20), (99, 69)]);
+        assert_eq!(
+            fog.drain_dirty(),
+            vec![TilePos { x: 10, y: 20 }, TilePos { x: 99, y: 69 }]
+        );
+
+        // Still in view, nothing to redraw
+        tick(&mut fog, &[(10, 20), (99, 69)]);
+        assert!(fog.drain_dirty().is_empty());
+
+        // Dropping back to explored is a change too
+        tick(&mut fog, &[(10, 20)]);
+        assert_eq!(fog.drain_dirty(), vec![TilePos { x: 99, y: 69 }]);
+    }
+
+    #[test]
+    fn enemies_are_hidden_outside_vision() {
+        let mut world = World::new();
+
+        let mut fog = FogOfWar::new(10, 10);
+        tick(&mut fog, &[(2, 2)]);
+        world.insert_resource(fog);
+
+        let seen = world
+            .spawn((Enemy, TilePos { x: 2, y: 2 }, Visibility::Hidden))
+            .id();
+        let fogged = world
+            .spawn((Enemy, TilePos { x: 7, y: 7 }, Visibility::Inherited))
+            .id();
+        let ours = world
+            .spawn((TilePos { x: 7, y: 7 }, Visibility::Inherited))
+            .id();
+
+        world.run_system_once(hide_fogged_enemies);
+
+        assert_eq!(world.get(seen), Some(&Visibility::Inherited));
+        assert_eq!(world.get(fogged), Some(&Visibility::Hidden));
+        assert_eq!(world.get(ours), Some(&Visibility::Inherited));
+    }
+
+    #[test]
+    fn open_ground_sees_the_whole_circle() {
+        let state = flat_state(SIZE as usize, 0.3);
+        let fog = seen_from(&state, 20, 20, 8);
+
+        // Every octant, and nothing past the radius
+        for y in 0..SIZE {
+            for x in 0..SIZE {
+                let (dx, dy) = (x as i32 - 20, y as i32 - 20);
+                assert_eq!(
+                    fog.is_visible(x, y),
+                    dx * dx + dy * dy <= 64,
+                    "({}, {})",
+                    x,
+                    y
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn map_edges_clip_vision() {
+        let state = flat_state(SIZE as usize, 0.3);
+        let fog = seen_from(&state, 0, 0, 5);
+        assert!(fog.is_visible(0, 0));
+        assert!(fog.is_visible(5, 0));
+        assert!(fog.is_visible(0, 5));
+        assert!(fog.is_visible(3, 4));
+        assert!(!fog.is_visible(4, 4));
+    }
+
+    #[test]
+    fn walls_block_what_is_behind_them() {
+        let mut state = flat_state(SIZE as usize, 0.3);
+        for y in 15..=25 {
+            state.map.set_value(23, y, 0.9);
+        }
+        let fog = seen_from(&state, 20, 20, 8);
+
+        // The wall itself is seen
+        assert!(fog.is_visible(23, 20));
+        assert!(fog.is_visible(23, 17));
+        assert!(!fog.is_visible(24, 20));
+        assert!(!fog.is_visible(27, 20));
+        assert!(!fog.is_visible(26, 18));
+
+        // The other side of the viewer doesn't care
+        assert!(fog.is_visible(13, 20));
+        assert!(fog.is_visible(20, 28));
+
+        // Small bumps don't block
+        let mut state = flat_state(SIZE as usize, 0.3);
+        state
+            .map
+            .set_value(23, 20, 0.3 + LINE_OF_SIGHT_MARGIN / 2.0);
+        assert!(seen_from(&state, 20, 20, 8).is_visible(26, 20));
+    }
+
+    #[test]
+    fn mountains_see_further() {
+        let mut state = flat_state(SIZE as usize, 0.3);
+        assert!(!seen_from(&state, 20, 20, 8).is_visible(20, 30));
+
+        state.biomes.set(20, 20, Biome::Mountain);
+        let fog = seen_from(&state, 20, 20, 8);
+        assert!(fog.is_visible(20, 20 + 8 + MOUNTAIN_VISION_BONUS as u32));
+        assert!(!fog.is_visible(20, 20 + 9 + MOUNTAIN_VISION_BONUS as u32));
+    }
+}
@@ -1,3 +1,4 @@
+use bevy::core::FrameCount;
 use bevy::prelude::*;
 use bevy::window::PrimaryWindow;
 use bevy_ecs_tilemap::prelude::*;
@@ -32,34 +33,28 @@ impl<S: States> Plugin for MapInteractionPlugin<S> {
 fn center_camera(
     mut query: Query<&mut Transform, With<Camera>>,
     mut ev_centercamera: EventReader<CenterCamera>,
+    chunks: Res<MapChunks>,
 ) {
     let mut camera_transform = query.single_mut();
     let event = ev_centercamera.read().next().unwrap();
 
-    // Map is centered at 0, 0 and size is 1000, 1000
-    // tile sizes are 32x32
-
-    let player_tower_x = event.x;
-    let player_tower_y = event.y;
-
-    let x = player_tower_x as f32 * 32.0;
-    let y = player_tower_y as f32 * 32.0;
-    // Map is centered, so subtract
-    camera_transform.translation = Vec3::new(x - 500.0 * 32.0, y - 500.0 * 32.0, 10.0);
+    let pos = chunks.tile_to_world(&event.loc);
+    camera_transform.translation = pos.extend(10.0);
 }
 
 pub fn map_click(
     mut ev_map_click: EventWriter<MapClick>,
     mut cursor: ResMut<CursorPos>,
-    tilemap_q: Query<(
-        &TilemapSize,
-        &TilemapGridSize,
-        &TilemapType,
-        &TileStorage,
-        &Transform,
-    )>,
+    chunks: Res<MapChunks>,
     q_windows: Query<&Window, With<PrimaryWindow>>,
+    box_select: Res<BoxSelect>,
+    frame: Res<FrameCount>,
 ) {
+    // That was the end of a box select, not an order
+    if box_select.swallows_click(&frame) {
+        return;
+    }
+
     let window = q_windows.single();
     if let Some(position) = q_windows.single().cursor_position() {
         // Get window size
@@ -75,28 +70,14 @@ pub fn map_click(
         return; // Not in this window
     }
 
-    for (map_size, grid_size, map_type, tile_storage, map_transform) in tilemap_q.iter() {
-        // Grab the cursor position from the `Res<CursorPos>`
-        let cursor_pos: Vec2 = cursor.mouse_position;
+    // Grab the cursor position from the `Res<CursorPos>`
+    let cursor_pos: Vec2 = cursor.mouse_position;
 
-        // Get window coordinates
-
-        // We need to make sure that the cursor's world position is correct relative to the map
-        // due to any map transformation.
-        let cursor_in_map_pos: Vec2 = {
-            // Extend the cursor_pos vec3 by 0.0 and 1.0
-            let cursor_pos = Vec4::from((cursor_pos, 0.0, 1.0));
-            let cursor_in_map_pos = map_transform.compute_matrix().inverse() * cursor_pos;
-            cursor_in_map_pos.xy()
-        };
-        // Once we have a world position we can transform it into a possible tile position.
-        if let Some(tile_pos) =
-            TilePos::from_world_pos(&cursor_in_map_pos, map_size, grid_size, map_type)
-        {
-            ev_map_click.send(MapClick(cursor_in_map_pos));
-            cursor.tile_position = tile_pos;
-            cursor.tile_position_real = cursor.mouse_position;
-            return;
-        }
+    // Chunks are all offset from the same origin, so work in whole map coordinates
+    if let Some(tile_pos) = chunks.world_to_tile(cursor_pos) {
+        let cursor_in_map_pos = cursor_pos - chunks.origin.translation.xy();
+        ev_map_click.send(MapClick(cursor_in_map_pos));
+        cursor.tile_position = tile_pos;
+        cursor.tile_position_real = cursor.mouse_position;
     }
 }
@@ -1,5 +1,33 @@
+pub mod autotile;
+pub mod biome;
+pub mod chunks;
+pub mod deposits;
+#[cfg(test)]
+pub mod fixtures;
+pub mod fog;
 pub mod generation;
 pub mod interaction;
+pub mod pathfinding;
+pub mod presets;
+pub mod quality;
+pub mod rivers;
+pub mod terrain;
+pub mod territory;
+pub mod towers;
+pub mod world;
 
+pub use autotile::*;
+pub use biome::*;
+pub use chunks::*;
+pub use deposits::*;
+pub use fog::*;
 pub use generation::*;
 pub use interaction::*;
+pub use pathfinding::*;
+pub use presets::*;
+pub use quality::*;
+pub use rivers::*;
+pub use terrain::*;
+pub use territory::*;
+pub use towers::*;
+pub use world::*;
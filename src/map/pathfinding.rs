@@ -0,0 +1,392 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+use bevy::utils::HashMap;
+use bevy_ecs_tilemap::prelude::TilePos;
+
+use crate::*;
+
+// Maps are a million tiles, so give up rather than flood a whole continent
+pub const MAX_SEARCH_NODES: usize = 250_000;
+
+// Cost to step onto a tile, None can't be entered at all. Rows are the unit
+// types (scout, excavation, attack), columns the terrain bands (deep water,
+// shallow water, lowland, hills, mountain). Slower ground is walked slower too.
+const MOVEMENT_COSTS: [[Option<f32>; 5]; 3] = [
+    // Quick on the flat, can wade
+    [None, Some(3.0), Some(0.8), Some(1.2), Some(2.5)],
+    // Hauling gear, mountains are a slog
+    [None, Some(3.0), Some(1.0), Some(1.5), Some(4.0)],
+    // Too heavy to ford anything
+    [None, None, Some(1.0), Some(1.5), Some(3.0)],
+];
+
+// Cheapest entry in MOVEMENT_COSTS, keeps the heuristic admissible
+const MIN_STEP_COST: f32 = 0.8;
+
+// Rough kinds of ground for movement, from the biome and the height
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TerrainBand {
+    DeepWater,
+    ShallowWater,
+    Lowland,
+    Hills,
+    Mountain,
+}
+
+impl TerrainBand {
+    pub fn at(state: &GameState, x: u32, y: u32) -> Self {
+        match state.biomes.get(x, y) {
+            Biome::DeepWater => TerrainBand::DeepWater,
+            Biome::ShallowWater => TerrainBand::ShallowWater,
+            _ => match state.map.get_value(x as usize, y as usize) {
+                v if v < 0.3 => TerrainBand::Lowland,
+                v if v < 0.6 => TerrainBand::Hills,
+                _ => TerrainBand::Mountain,
+            },
+        }
+    }
+
+    pub fn cost(&self, unit_type: UnitType) -> Option<f32> {
+        let row = match unit_type {
+            UnitType::Scout => 0,
+            UnitType::Excavation => 1,
+            UnitType::Attack => 2,
+        };
+        MOVEMENT_COSTS[row][*self as usize]
+    }
+}
+
+pub fn terrain_cost(state: &GameState, unit_type: UnitType, x: u32, y: u32) -> Option<f32> {
+    TerrainBand::at(state, x, y).cost(unit_type)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathError {
+    OffMap,
+    // The destination itself can't be stood on
+    Impassable,
+    // Nothing connects the two, like another island
+    Unreachable,
+    TooFar,
+}
+
+impl std::fmt::Display for PathError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PathError::OffMap => write!(f, "Destination is off the map"),
+            PathError::Impassable => write!(f, "Destination can't be walked on"),
+            PathError::Unreachable => write!(f, "No way to get there over land"),
+            PathError::TooFar => write!(f, "Gave up looking for a path, too far"),
+        }
+    }
+}
+
+impl std::error::Error for PathError {}
+
+// Min-heap entry for the open set
+struct OpenNode {
+    estimate: f32,
+    idx: usize,
+}
+
+impl PartialEq for OpenNode {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for OpenNode {}
+
+impl PartialOrd for OpenNode {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for OpenNode {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Reversed so BinaryHeap pops the cheapest node first
+        other
+            .estimate
+            .total_cmp(&self.estimate)
+            .then_with(|| other.idx.cmp(&self.idx))
+    }
+}
+
+// Straight moves cost 1, diagonals sqrt 2
+fn octile(dx: u32, dy: u32) -> f32 {
+    let (low, high) = (dx.min(dy) as f32, dx.max(dy) as f32);
+    high - low + low * std::f32::consts::SQRT_2
+}
+
+// A* over the 8 neighbours, `cost` is what it takes to step onto a tile (None is
+// impassable). Diagonals can't cut past an impassable corner. Returns the
+// waypoints after `start`, only where the path turns, ending at `goal`.
+pub fn find_path(
+    state: &GameState,
+    start: TilePos,
+    goal: TilePos,
+    cost: impl Fn(u32, u32) -> Option<f32>,
+) -> Result<Vec<TilePos>, PathError> {
+    let (width, height) = state.map.size();
+    let in_map = |pos: &TilePos| (pos.x as usize) < width && (pos.y as usize) < height;
+    if !in_map(&start) || !in_map(&goal) {
+        return Err(PathError::OffMap);
+    }
+
+    if cost(goal.x, goal.y).is_none() {
+        return Err(PathError::Impassable);
+    }
+
+    if start == goal {
+        return Ok(vec![]);
+    }
+
+    let index = |x: u32, y: u32| x as usize + y as usize * width;
+    let heuristic = |x: u32, y: u32| octile(x.abs_diff(goal.x), y.abs_diff(goal.y)) * MIN_STEP_COST;
+
+    // Only what's been reached, (cheapest cost so far, tile it came from). Whole
+    // map arrays would be megabytes for every order.
+    let mut visited: HashMap<usize, (f32, usize)> = HashMap::new();
+    let mut open = BinaryHeap::new();
+
+    let start_idx = index(start.x, start.y);
+    let goal_idx = index(goal.x, goal.y);
+    visited.insert(start_idx, (0.0, usize::MAX));
+    open.push(OpenNode {
+        estimate: heuristic(start.x, start.y),
+        idx: start_idx,
+    });
+
+    let mut searched = 0;
+    while let Some(OpenNode { estimate, idx }) = open.pop() {
+        if idx == goal_idx {
+            return Ok(waypoints(&visited, goal_idx, width));
+        }
+
+        let (x, y) = ((idx % width) as u32, (idx / width) as u32);
+        let so_far = visited[&idx].0;
+
+        // Stale entry, already found a cheaper way here
+        if estimate > so_far + heuristic(x, y) {
+            continue;
+        }
+
+        searched += 1;
+        if searched > MAX_SEARCH_NODES {
+            return Err(PathError::TooFar);
+        }
+
+        for dx in -1..=1i32 {
+            for dy in -1..=1i32 {
+                if dx == 0 && dy == 0 {
+                    continue;
+                }
+                let (nx, ny) = (x as i32 + dx, y as i32 + dy);
+                if nx < 0 || ny < 0 || nx >= width as i32 || ny >= height as i32 {
+                    continue;
+                }
+                let (nx, ny) = (nx as u32, ny as u32);
+
+                let Some(step) = cost(nx, ny) else {
+                    continue;
+                };
+
+                if dx != 0 && dy != 0 {
+                    let corner_a = cost(nx, y);
+                    let corner_b = cost(x, ny);
+                    if corner_a.is_none() || corner_b.is_none() {
+                        continue;
+                    }
+                }
+
+                let distance = if dx != 0 && dy != 0 {
+                    std::f32::consts::SQRT_2
+                } else {
+                    1.0
+                };
+
+                let next = index(nx, ny);
+                let total = so_far + step * distance;
+                if visited.get(&next).map_or(true, |(best, _)| total < *best) {
+                    visited.insert(next, (total, idx));
+                    open.push(OpenNode {
+                        estimate: total + heuristic(nx, ny),
+                        idx: next,
+                    });
+                }
+            }
+        }
+    }
+
+    Err(PathError::Unreachable)
+}
+
+// Walks back from the goal, dropping tiles in the middle of straight runs
+fn waypoints(
+    visited: &HashMap<usize, (f32, usize)>,
+    goal_idx: usize,
+    width: usize,
+) -> Vec<TilePos> {
+    let to_pos = |idx: usize| TilePos {
+        x: (idx % width) as u32,
+        y: (idx / width) as u32,
+    };
+
+    let mut tiles = vec![to_pos(goal_idx)];
+    let mut idx = goal_idx;
+    // The start came from nowhere
+    while let Some(&(_, from)) = visited.get(&idx) {
+        if from == usize::MAX {
+            break;
+        }
+        idx = from;
+        tiles.push(to_pos(idx));
+    }
+    tiles.reverse();
+
+    let step = |a: &TilePos, b: &TilePos| (b.x as i32 - a.x as i32, b.y as i32 - a.y as i32);
+
+    let mut path = Vec::new();
+    for i in 1..tiles.len() {
+        let is_last = i == tiles.len() - 1;
+        if is_last || step(&tiles[i - 1], &tiles[i]) != step(&tiles[i], &tiles[i + 1]) {
+            path.push(tiles[i]);
+        }
+    }
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::fixtures::*;
+
+    const SIZE: usize = 12;
+
+    fn wall(state: &mut GameState, x: u32, ys: std::ops::Range<u32>, biome: Biome) {
+        for y in ys {
+            state.biomes.set(x, y, biome);
+        }
+    }
+
+    fn path(
+        state: &GameState,
+        unit_type: UnitType,
+        start: (u32, u32),
+        goal: (u32, u32),
+    ) -> Result<Vec<TilePos>, PathError> {
+        find_path(
+            state,
+            TilePos {
+                x: start.0,
+                y: start.1,
+            },
+            TilePos {
+                x: goal.0,
+                y: goal.1,
+            },
+            |x, y| terrain_cost(state, unit_type, x, y),
+        )
+    }
+
+    // Every tile along the straight runs between waypoints
+    fn walk(start: (u32, u32), waypoints: &[TilePos]) -> Vec<(u32, u32)> {
+        let mut tiles = vec![start];
+        let (mut x, mut y) = start;
+        for waypoint in waypoints {
+            while (x, y) != (waypoint.x, waypoint.y) {
+                x = (x as i32 + (waypoint.x as i32 - x as i32).signum()) as u32;
+                y = (y as i32 + (waypoint.y as i32 - y as i32).signum()) as u32;
+                tiles.push((x, y));
+            }
+        }
+        tiles
+    }
+
+    #[test]
+    fn already_there() {
+        let state = flat_state(SIZE, LOWLAND);
+        assert_eq!(path(&state, UnitType::Scout, (3, 3), (3, 3)), Ok(vec![]));
+    }
+
+    #[test]
+    fn straight_lines_are_one_waypoint() {
+        let state = flat_state(SIZE, LOWLAND);
+        assert_eq!(
+            path(&state, UnitType::Scout, (1, 5), (9, 5)),
+            Ok(vec![TilePos { x: 9, y: 5 }])
+        );
+        assert_eq!(
+            path(&state, UnitType::Scout, (1, 1), (6, 6)),
+            Ok(vec![TilePos { x: 6, y: 6 }])
+        );
+    }
+
+    #[test]
+    fn routes_around_water() {
+        let mut state = flat_state(SIZE, LOWLAND);
+        wall(&mut state, 5, 0..SIZE as u32 - 1, Biome::DeepWater);
+
+        let waypoints = path(&state, UnitType::Scout, (2, 2), (8, 2)).unwrap();
+        assert_eq!(waypoints.last(), Some(&TilePos { x: 8, y: 2 }));
+
+        let tiles = walk((2, 2), &waypoints);
+        assert!(tiles
+            .iter()
+            .all(|(x, y)| !state.biomes.get(*x, *y).is_water()));
+        // Only gap is the top row
+        assert!(tiles.contains(&(5, SIZE as u32 - 1)));
+    }
+
+    #[test]
+    fn no_way_across() {
+        let mut state = flat_state(SIZE, LOWLAND);
+        wall(&mut state, 5, 0..SIZE as u32, Biome::DeepWater);
+        assert_eq!(
+            path(&state, UnitType::Scout, (2, 2), (8, 2)),
+            Err(PathError::Unreachable)
+        );
+
+        // Scouts can wade shallow water, attack units can't
+        let mut state = flat_state(SIZE, LOWLAND);
+        wall(&mut state, 5, 0..SIZE as u32, Biome::ShallowWater);
+        assert!(path(&state, UnitType::Scout, (2, 2), (8, 2)).is_ok());
+        assert_eq!(
+            path(&state, UnitType::Attack, (2, 2), (8, 2)),
+            Err(PathError::Unreachable)
+        );
+    }
+
+    #[test]
+    fn bad_destinations() {
+        let mut state = flat_state(SIZE, LOWLAND);
+        state.biomes.set(8, 8, Biome::DeepWater);
+        assert_eq!(
+            path(&state, UnitType::Scout, (2, 2), (8, 8)),
+            Err(PathError::Impassable)
+        );
+        assert_eq!(
+            path(&state, UnitType::Scout, (2, 2), (SIZE as u32, 2)),
+            Err(PathError::OffMap)
+        );
+    }
+
+    #[test]
+    fn detours_around_mountains() {
+        let mut state = flat_state(SIZE, LOWLAND);
+        for x in 4..=7 {
+            for y in 3..=8 {
+                state.map.set_value(x, y, 0.7);
+            }
+        }
+        assert_eq!(TerrainBand::at(&state, 5, 5), TerrainBand::Mountain);
+
+        let waypoints = path(&state, UnitType::Scout, (1, 5), (10, 5)).unwrap();
+        let tiles = walk((1, 5), &waypoints);
+        assert!(tiles
+            .iter()
+            .all(|(x, y)| TerrainBand::at(&state, *x, *y) != TerrainBand::Mountain));
+    }
+}
@@ -0,0 +1,176 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+pub const PRESET_DIR: &str = "assets/worldgen";
+
+// Compiled in so presets still work where there's no filesystem (web)
+const BUILTIN_PRESETS: [(&str, &str); 3] = [
+    (
+        "continent",
+        include_str!("../../assets/worldgen/continent.yaml"),
+    ),
+    (
+        "archipelago",
+        include_str!("../../assets/worldgen/archipelago.yaml"),
+    ),
+    (
+        "highlands",
+        include_str!("../../assets/worldgen/highlands.yaml"),
+    ),
+];
+
+// Missing fields fall back to the defaults, misspelt ones are an error
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WorldGenPreset {
+    pub name: String,
+
+    // Elevation noise
+    pub octaves: usize,
+    pub frequency: f64,
+    pub persistence: f64,
+    pub x_bounds: (f64, f64),
+    pub y_bounds: (f64, f64),
+
+    // Maps with an average elevation outside of this get rescaled
+    pub average_min: f64,
+    pub average_max: f64,
+
+    pub erosion_iterations: usize,
+    pub rainfall: f64,
+
+    // Ranges are min inclusive, max exclusive
+    pub enemy_towers: (u32, u32),
+    pub treasure_spots: (u32, u32),
+
+    // In tiles, between any two towers and between the player and enemy towers
+    pub min_tower_spacing: u32,
+    pub min_player_distance: u32,
+
+    // Treasure spots are placed in clusters of ruins
+    pub ruin_cluster_size: (u32, u32),
+    pub ruin_cluster_radius: u32,
+
+    // Ore veins and mana crystals, amount is how much each one holds
+    pub deposit_spots: (u32, u32),
+    pub deposit_amount: (u32, u32),
+
+    // Maps failing these get regenerated (from the same seed) up to max_regen_attempts
+    // times, ratios are of the whole map
+    pub min_land_ratio: f64,
+    pub max_mountain_ratio: f64,
+    pub min_landmass_ratio: f64,
+    pub min_tower_area_ratio: f64,
+    pub max_regen_attempts: u32,
+}
+
+impl Default for WorldGenPreset {
+    fn default() -> Self {
+        Self {
+            name: "continent".to_string(),
+            octaves: 6,
+            frequency: 1.0,
+            persistence: 0.5,
+            x_bounds: (-1.0, 1.0),
+            y_bounds: (-1.0, 1.0),
+            average_min: 0.3,
+            average_max: 0.7,
+            erosion_iterations: 2,
+            rainfall: 0.01,
+            enemy_towers: (10, 20),
+            treasure_spots: (200, 500),
+            min_tower_spacing: 40,
+            min_player_distance: 100,
+            ruin_cluster_size: (3, 8),
+            ruin_cluster_radius: 6,
+            deposit_spots: (150, 300),
+            deposit_amount: (100, 400),
+            min_land_ratio: 0.3,
+            max_mountain_ratio: 0.4,
+            min_landmass_ratio: 0.1,
+            min_tower_area_ratio: 0.02,
+            max_regen_attempts: 3,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum PresetError {
+    NotFound(String),
+    Invalid(serde_yaml::Error),
+}
+
+impl std::fmt::Display for PresetError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PresetError::NotFound(name) => write!(f, "World generation preset not found: {}", name),
+            PresetError::Invalid(e) => write!(f, "Invalid world generation preset: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for PresetError {}
+
+impl WorldGenPreset {
+    // Looks in assets/worldgen/<name>.yaml first, then the built in presets
+    pub fn load(name: &str) -> Result<Self, PresetError> {
+        let path = Path::new(PRESET_DIR).join(format!("{}.yaml", name));
+
+        match std::fs::read_to_string(&path) {
+            Ok(yaml) => Self::from_yaml(&yaml),
+            Err(_) => {
+                let yaml = BUILTIN_PRESETS
+                    .iter()
+                    .find(|(preset, _)| *preset == name)
+                    .map(|(_, yaml)| *yaml)
+                    .ok_or_else(|| PresetError::NotFound(name.to_string()))?;
+                Self::from_yaml(yaml)
+            }
+        }
+    }
+
+    pub fn from_yaml(yaml: &str) -> Result<Self, PresetError> {
+        serde_yaml::from_str(yaml).map_err(PresetError::Invalid)
+    }
+
+    pub fn builtin_names() -> impl Iterator<Item = &'static str> {
+        BUILTIN_PRESETS.iter().map(|(name, _)| *name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builtin_presets_load() {
+        // Compiled in and from assets/worldgen, they're the same files
+        for (name, yaml) in BUILTIN_PRESETS {
+            let preset =
+                WorldGenPreset::from_yaml(yaml).unwrap_or_else(|e| panic!("{}: {}", name, e));
+            assert_eq!(preset.name, name);
+            assert_eq!(WorldGenPreset::load(name).unwrap().name, name);
+        }
+    }
+
+    #[test]
+    fn unknown_presets_are_not_found() {
+        assert!(matches!(
+            WorldGenPreset::load("atlantis"),
+            Err(PresetError::NotFound(name)) if name == "atlantis"
+        ));
+    }
+
+    #[test]
+    fn typos_are_invalid() {
+        assert!(matches!(
+            WorldGenPreset::from_yaml("name: typo\noctave: 4\n"),
+            Err(PresetError::Invalid(_))
+        ));
+        assert_eq!(
+            WorldGenPreset::from_yaml("octaves: 4\n").unwrap().octaves,
+            4
+        );
+    }
+}
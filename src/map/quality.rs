@@ -0,0 +1,337 @@
+use std::collections::VecDeque;
+
+use noise::utils::NoiseMap;
+use serde::Serialize;
+
+use crate::*;
+
+#[derive(Debug, Clone, Serialize)]
+pub enum QualityIssue {
+    TooLittleLand {
+        ratio: f64,
+        min: f64,
+    },
+    TooMuchMountain {
+        ratio: f64,
+        max: f64,
+    },
+    LandmassTooSmall {
+        ratio: f64,
+        min: f64,
+    },
+    TowerAreaTooSmall {
+        tower: (u32, u32),
+        ratio: f64,
+        min: f64,
+    },
+}
+
+impl std::fmt::Display for QualityIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            QualityIssue::TooLittleLand { ratio, min } => {
+                write!(f, "Land ratio {:.2} is below {:.2}", ratio, min)
+            }
+            QualityIssue::TooMuchMountain { ratio, max } => {
+                write!(f, "Mountain ratio {:.2} is above {:.2}", ratio, max)
+            }
+            QualityIssue::LandmassTooSmall { ratio, min } => {
+                write!(f, "Largest landmass {:.2} is below {:.2}", ratio, min)
+            }
+            QualityIssue::TowerAreaTooSmall { tower, ratio, min } => write!(
+                f,
+                "Tower at {:?} can only reach {:.2} of the map, below {:.2}",
+                tower, ratio, min
+            ),
+        }
+    }
+}
+
+// How playable a generated map is. Areas are in tiles, ratios are of the whole map.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct MapQualityReport {
+    pub land_ratio: f64,
+    pub mountain_ratio: f64,
+    pub largest_landmass: usize,
+    pub largest_landmass_ratio: f64,
+    pub player_tower_area: usize,
+    pub enemy_tower_areas: Vec<usize>,
+    // How many terrains were generated before this one was kept
+    pub attempts: u32,
+    pub issues: Vec<QualityIssue>,
+}
+
+impl MapQualityReport {
+    pub fn measure(
+        map: &NoiseMap,
+        biomes: &BiomeMap,
+        rivers: &RiverNetwork,
+        towers: &TowerPlacement,
+        preset: &WorldGenPreset,
+    ) -> Self {
+        let (width, height) = map.size();
+        let total = (width * height).max(1) as f64;
+
+        let landmasses = Landmasses::label(map, rivers);
+
+        let mut mountains = 0;
+        for y in 0..height as u32 {
+            for x in 0..width as u32 {
+                if matches!(biomes.get(x, y), Biome::Mountain | Biome::Snow) {
+                    mountains += 1;
+                }
+            }
+        }
+
+        let land: usize = landmasses.sizes.iter().sum();
+        let largest_landmass = landmasses.sizes.iter().copied().max().unwrap_or(0);
+
+        let mut report = Self {
+            land_ratio: land as f64 / total,
+            mountain_ratio: mountains as f64 / total,
+            largest_landmass,
+            largest_landmass_ratio: largest_landmass as f64 / total,
+            player_tower_area: landmasses.area_at(towers.player),
+            enemy_tower_areas: towers
+                .enemies
+                .iter()
+                .map(|tower| landmasses.area_at(*tower))
+                .collect(),
+            attempts: 1,
+            issues: vec![],
+        };
+
+        report.issues = report.find_issues(towers, preset, total);
+        report
+    }
+
+    pub fn is_acceptable(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    fn find_issues(
+        &self,
+        towers: &TowerPlacement,
+        preset: &WorldGenPreset,
+        total: f64,
+    ) -> Vec<QualityIssue> {
+        let mut issues = vec![];
+
+        if self.land_ratio < preset.min_land_ratio {
+            issues.push(QualityIssue::TooLittleLand {
+                ratio: self.land_ratio,
+                min: preset.min_land_ratio,
+            });
+        }
+
+        if self.mountain_ratio > preset.max_mountain_ratio {
+            issues.push(QualityIssue::TooMuchMountain {
+                ratio: self.mountain_ratio,
+                max: preset.max_mountain_ratio,
+            });
+        }
+
+        if self.largest_landmass_ratio < preset.min_landmass_ratio {
+            issues.push(QualityIssue::LandmassTooSmall {
+                ratio: self.largest_landmass_ratio,
+                min: preset.min_landmass_ratio,
+            });
+        }
+
+        let tower_areas = std::iter::once((towers.player, self.player_tower_area)).chain(
+            towers
+                .enemies
+                .iter()
+                .copied()
+                .zip(self.enemy_tower_areas.iter().copied()),
+        );
+        for (tower, area) in tower_areas {
+            let ratio = area as f64 / total;
+            if ratio < preset.min_tower_area_ratio {
+                issues.push(QualityIssue::TowerAreaTooSmall {
+                    tower,
+                    ratio,
+                    min: preset.min_tower_area_ratio,
+                });
+            }
+        }
+
+        issues
+    }
+}
+
+// Connected land (4-neighbour, rivers can be forded), labelled once so every tower's
+// reachable area is just a lookup
+struct Landmasses {
+    width: usize,
+    labels: Vec<Option<u32>>,
+    sizes: Vec<usize>,
+}
+
+impl Landmasses {
+    fn label(map: &NoiseMap, rivers: &RiverNetwork) -> Self {
+        let (width, height) = map.size();
+        let mut labels: Vec<Option<u32>> = vec![None; width * height];
+        let mut sizes = vec![];
+        let mut queue = VecDeque::new();
+
+        for start in 0..width * height {
+            let (x, y) = ((start % width) as u32, (start / width) as u32);
+            if labels[start].is_some() || !is_land(map, rivers, x, y) {
+                continue;
+            }
+
+            let label = sizes.len() as u32;
+            let mut size = 0;
+            labels[start] = Some(label);
+            queue.push_back((x, y));
+
+            while let Some((x, y)) = queue.pop_front() {
+                size += 1;
+                for (dx, dy) in [(-1, 0), (1, 0), (0, -1), (0, 1)] {
+                    let nx = x as i32 + dx;
+                    let ny = y as i32 + dy;
+                    if nx < 0 || ny < 0 || nx >= width as i32 || ny >= height as i32 {
+                        continue;
+                    }
+                    let (nx, ny) = (nx as u32, ny as u32);
+                    let idx = nx as usize + ny as usize * width;
+                    if labels[idx].is_none() && is_land(map, rivers, nx, ny) {
+                        labels[idx] = Some(label);
+                        queue.push_back((nx, ny));
+                    }
+                }
+            }
+
+            sizes.push(size);
+        }
+
+        Self {
+            width,
+            labels,
+            sizes,
+        }
+    }
+
+    fn area_at(&self, (x, y): (u32, u32)) -> usize {
+        self.labels
+            .get(x as usize + y as usize * self.width)
+            .copied()
+            .flatten()
+            .map_or(0, |label| self.sizes[label as usize])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 20x10 of sea with an 8x8 island, a 5x5 island and a single rock
+    fn islands() -> (NoiseMap, BiomeMap, RiverNetwork) {
+        let mut map = NoiseMap::new(20, 10);
+        for y in 0..10 {
+            for x in 0..20 {
+                let big = (1..9).contains(&x) && (1..9).contains(&y);
+                let small = (11..16).contains(&x) && (1..6).contains(&y);
+                let rock = (x, y) == (18, 8);
+                map.set_value(x, y, if big || small || rock { 0.3 } else { 0.0 });
+            }
+        }
+
+        let mut biomes = BiomeMap::generate(0, &map);
+        for y in 0..10 {
+            for x in 0..20 {
+                if map.get_value(x as usize, y as usize) > 0.0 {
+                    biomes.set(x, y, Biome::Grassland);
+                }
+            }
+        }
+        // A row of peaks on the big island
+        for x in 1..9 {
+            biomes.set(x, 1, Biome::Mountain);
+        }
+
+        (map, biomes, RiverNetwork::default())
+    }
+
+    fn towers() -> TowerPlacement {
+        TowerPlacement {
+            player: (2, 2),
+            enemies: vec![(12, 2), (18, 8)],
+        }
+    }
+
+    #[test]
+    fn landmasses_are_labelled_apart() {
+        let (map, _, rivers) = islands();
+        let landmasses = Landmasses::label(&map, &rivers);
+
+        assert_eq!(landmasses.sizes, vec![64, 25, 1]);
+        assert_eq!(landmasses.area_at((2, 2)), 64);
+        assert_eq!(landmasses.area_at((15, 5)), 25);
+        assert_eq!(landmasses.area_at((18, 8)), 1);
+        // Sea, and off the map
+        assert_eq!(landmasses.area_at((0, 0)), 0);
+        assert_eq!(landmasses.area_at((30, 30)), 0);
+    }
+
+    #[test]
+    fn stranded_towers_fail() {
+        let (map, biomes, rivers) = islands();
+        let preset = WorldGenPreset::default();
+        let report = MapQualityReport::measure(&map, &biomes, &rivers, &towers(), &preset);
+
+        assert_eq!(report.land_ratio, 90.0 / 200.0);
+        assert_eq!(report.mountain_ratio, 8.0 / 200.0);
+        assert_eq!(report.largest_landmass, 64);
+        assert_eq!(report.player_tower_area, 64);
+        assert_eq!(report.enemy_tower_areas, vec![25, 1]);
+
+        // The rock is 0.005 of the map, below the default 0.02
+        assert!(!report.is_acceptable());
+        assert_eq!(report.issues.len(), 1);
+        assert!(matches!(
+            report.issues[0],
+            QualityIssue::TowerAreaTooSmall { tower: (18, 8), .. }
+        ));
+    }
+
+    #[test]
+    fn thresholds_decide_rerolls() {
+        let (map, biomes, rivers) = islands();
+        let measure = |preset: &WorldGenPreset| {
+            MapQualityReport::measure(&map, &biomes, &rivers, &towers(), preset)
+        };
+
+        // Right on a threshold still passes
+        let lenient = WorldGenPreset {
+            min_land_ratio: 0.45,
+            max_mountain_ratio: 0.04,
+            min_landmass_ratio: 0.32,
+            min_tower_area_ratio: 0.005,
+            ..Default::default()
+        };
+        assert!(measure(&lenient).is_acceptable());
+
+        let strict = WorldGenPreset {
+            min_land_ratio: 0.5,
+            max_mountain_ratio: 0.03,
+            min_landmass_ratio: 0.4,
+            min_tower_area_ratio: 0.2,
+            ..Default::default()
+        };
+        let issues = measure(&strict).issues;
+        assert!(matches!(issues[0], QualityIssue::TooLittleLand { .. }));
+        assert!(matches!(issues[1], QualityIssue::TooMuchMountain { .. }));
+        assert!(matches!(issues[2], QualityIssue::LandmassTooSmall { .. }));
+        // The player's island is big enough, the other two aren't
+        let towers: Vec<(u32, u32)> = issues[3..]
+            .iter()
+            .map(|issue| match issue {
+                QualityIssue::TowerAreaTooSmall { tower, .. } => *tower,
+                other => panic!("{}", other),
+            })
+            .collect();
+        assert_eq!(towers, vec![(12, 2), (18, 8)]);
+    }
+}
@@ -0,0 +1,442 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+use bevy_ecs_tilemap::prelude::TilePos;
+use noise::utils::NoiseMap;
+
+// Anything below this is ocean (same as the water bands in get_index)
+pub const SEA_LEVEL: f64 = 0.08;
+
+// How much of the drop to the downstream cell a unit of water removes
+const EROSION_RATE: f64 = 0.05;
+
+// Depressions shallower than this are just bumpy ground, not lakes
+const LAKE_MIN_DEPTH: f64 = 0.005;
+
+// Keeps filled flats draining towards their outlet
+const FLAT_EPSILON: f64 = 1e-7;
+
+const NEIGHBOURS: [(i32, i32); 8] = [
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+    (-1, 0),
+    (1, 0),
+    (-1, 1),
+    (0, 1),
+    (1, 1),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WaterCell {
+    #[default]
+    Dry,
+    River,
+    Lake,
+}
+
+#[derive(Debug, Clone)]
+pub struct River {
+    // Source first, mouth (sea, lake or confluence) last
+    pub points: Vec<(u32, u32)>,
+    // Accumulated rainfall at the mouth
+    pub flow: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct Lake {
+    pub cells: Vec<(u32, u32)>,
+    pub level: f64,
+}
+
+#[derive(Default)]
+pub struct RiverNetwork {
+    pub rivers: Vec<River>,
+    pub lakes: Vec<Lake>,
+    width: usize,
+    height: usize,
+    cells: Vec<WaterCell>,
+}
+
+impl RiverNetwork {
+    pub fn get(&self, x: u32, y: u32) -> WaterCell {
+        let (x, y) = (x as usize, y as usize);
+        if x < self.width && y < self.height {
+            self.cells[x + y * self.width]
+        } else {
+            WaterCell::Dry
+        }
+    }
+
+    pub fn get_tile(&self, tile_pos: &TilePos) -> WaterCell {
+        self.get(tile_pos.x, tile_pos.y)
+    }
+
+    pub fn is_river(&self, x: u32, y: u32) -> bool {
+        self.get(x, y) == WaterCell::River
+    }
+
+    pub fn is_lake(&self, x: u32, y: u32) -> bool {
+        self.get(x, y) == WaterCell::Lake
+    }
+
+    pub fn is_water(&self, x: u32, y: u32) -> bool {
+        self.get(x, y) != WaterCell::Dry
+    }
+
+    // Only the cell, the River and Lake lists still describe what was generated
+    pub fn set(&mut self, x: u32, y: u32, cell: WaterCell) {
+        let (x, y) = (x as usize, y as usize);
+        if x < self.width && y < self.height {
+            self.cells[x + y * self.width] = cell;
+        }
+    }
+}
+
+// Min-heap entry for the priority flood
+struct FloodCell {
+    height: f64,
+    idx: usize,
+}
+
+impl PartialEq for FloodCell {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for FloodCell {}
+
+impl PartialOrd for FloodCell {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for FloodCell {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Reversed so BinaryHeap pops the lowest cell first
+        other
+            .height
+            .total_cmp(&self.height)
+            .then_with(|| other.idx.cmp(&self.idx))
+    }
+}
+
+struct Drainage {
+    // Heights with every depression filled up to its spill point
+    filled: Vec<f64>,
+    downstream: Vec<Option<usize>>,
+    accumulation: Vec<f64>,
+    // Highest first, so water can be pushed downhill in one pass
+    order: Vec<usize>,
+}
+
+fn neighbours(idx: usize, width: usize, height: usize) -> impl Iterator<Item = usize> {
+    let x = (idx % width) as i32;
+    let y = (idx / width) as i32;
+    NEIGHBOURS.iter().filter_map(move |(dx, dy)| {
+        let nx = x + dx;
+        let ny = y + dy;
+        if nx >= 0 && nx < width as i32 && ny >= 0 && ny < height as i32 {
+            Some(nx as usize + ny as usize * width)
+        } else {
+            None
+        }
+    })
+}
+
+fn drainage(heights: &[f64], width: usize, height: usize, rainfall_amount: f64) -> Drainage {
+    let n = width * height;
+
+    // Priority flood (Barnes et al.) from the map edges and the sea inwards
+    let mut filled = heights.to_vec();
+    let mut visited = vec![false; n];
+    let mut outlet = vec![false; n];
+    let mut heap = BinaryHeap::new();
+
+    for idx in 0..n {
+        let (x, y) = (idx % width, idx / width);
+        if x == 0 || y == 0 || x == width - 1 || y == height - 1 || heights[idx] < SEA_LEVEL {
+            visited[idx] = true;
+            outlet[idx] = true;
+            heap.push(FloodCell {
+                height: heights[idx],
+                idx,
+            });
+        }
+    }
+
+    while let Some(FloodCell { height: level, idx }) = heap.pop() {
+        for next in neighbours(idx, width, height) {
+            if visited[next] {
+                continue;
+            }
+            visited[next] = true;
+            filled[next] = heights[next].max(level + FLAT_EPSILON);
+            heap.push(FloodCell {
+                height: filled[next],
+                idx: next,
+            });
+        }
+    }
+
+    // Every non-outlet cell drains to its lowest neighbour on the filled surface
+    let mut downstream = vec![None; n];
+    for idx in 0..n {
+        if outlet[idx] {
+            continue;
+        }
+        let mut lowest = filled[idx];
+        for next in neighbours(idx, width, height) {
+            if filled[next] < lowest {
+                lowest = filled[next];
+                downstream[idx] = Some(next);
+            }
+        }
+    }
+
+    let mut order: Vec<usize> = (0..n).collect();
+    order.sort_unstable_by(|a, b| filled[*b].total_cmp(&filled[*a]));
+
+    let mut accumulation = vec![rainfall_amount; n];
+    for &idx in order.iter() {
+        if let Some(down) = downstream[idx] {
+            accumulation[down] += accumulation[idx];
+        }
+    }
+
+    Drainage {
+        filled,
+        downstream,
+        accumulation,
+        order,
+    }
+}
+
+pub fn simulate_rainfall_river_generation_erosion(
+    mut map: NoiseMap,
+    iterations: usize,
+    rainfall_amount: f64,
+) -> (NoiseMap, RiverNetwork) {
+    let (width, height) = map.size();
+    let n = width * height;
+    let mut heights: Vec<f64> = map.iter().copied().collect();
+
+    // Stream power erosion, more water carves deeper channels
+    for _ in 0..iterations {
+        let drainage = drainage(&heights, width, height, rainfall_amount);
+        for &idx in drainage.order.iter() {
+            if let Some(down) = drainage.downstream[idx] {
+                let drop = heights[idx] - heights[down];
+                if drop > 0.0 {
+                    let rate = (drainage.accumulation[idx].sqrt() * EROSION_RATE).min(0.5);
+                    heights[idx] -= drop * rate;
+                }
+            }
+        }
+    }
+
+    for (val, h) in map.iter_mut().zip(heights.iter()) {
+        *val = *h;
+    }
+
+    let drainage = drainage(&heights, width, height, rainfall_amount);
+
+    // Rivers need a decent catchment, scaled with the map so small maps still get some
+    let river_threshold = ((n / 2500).max(50)) as f64 * rainfall_amount;
+
+    let mut cells = vec![WaterCell::Dry; n];
+    for idx in 0..n {
+        if heights[idx] < SEA_LEVEL {
+            continue;
+        }
+        if drainage.filled[idx] - heights[idx] > LAKE_MIN_DEPTH {
+            cells[idx] = WaterCell::Lake;
+        } else if drainage.accumulation[idx] >= river_threshold {
+            cells[idx] = WaterCell::River;
+        }
+    }
+
+    let lakes = extract_lakes(&cells, &drainage.filled, width, height);
+    let rivers = extract_rivers(&cells, &drainage, width);
+
+    log::info!("Rivers: {}, Lakes: {}", rivers.len(), lakes.len());
+
+    (
+        map,
+        RiverNetwork {
+            rivers,
+            lakes,
+            width,
+            height,
+            cells,
+        },
+    )
+}
+
+fn to_xy(idx: usize, width: usize) -> (u32, u32) {
+    ((idx % width) as u32, (idx / width) as u32)
+}
+
+fn extract_lakes(cells: &[WaterCell], filled: &[f64], width: usize, height: usize) -> Vec<Lake> {
+    let mut lakes = vec![];
+    let mut seen = vec![false; cells.len()];
+
+    for start in 0..cells.len() {
+        if seen[start] || cells[start] != WaterCell::Lake {
+            continue;
+        }
+
+        seen[start] = true;
+        let mut stack = vec![start];
+        let mut lake = Lake {
+            cells: vec![],
+            level: filled[start],
+        };
+
+        while let Some(idx) = stack.pop() {
+            lake.cells.push(to_xy(idx, width));
+            lake.level = lake.level.max(filled[idx]);
+            for next in neighbours(idx, width, height) {
+                if !seen[next] && cells[next] == WaterCell::Lake {
+                    seen[next] = true;
+                    stack.push(next);
+                }
+            }
+        }
+
+        lakes.push(lake);
+    }
+
+    lakes
+}
+
+fn extract_rivers(cells: &[WaterCell], drainage: &Drainage, width: usize) -> Vec<River> {
+    // Sources are river cells nothing else flows into
+    let mut upstream_rivers = vec![0_u8; cells.len()];
+    for idx in 0..cells.len() {
+        if cells[idx] != WaterCell::River {
+            continue;
+        }
+        if let Some(down) = drainage.downstream[idx] {
+            if cells[down] == WaterCell::River {
+                upstream_rivers[down] = upstream_rivers[down].saturating_add(1);
+            }
+        }
+    }
+
+    let mut rivers = vec![];
+    let mut visited = vec![false; cells.len()];
+
+    for &source in drainage.order.iter() {
+        if cells[source] != WaterCell::River || upstream_rivers[source] > 0 {
+            continue;
+        }
+
+        let mut points = vec![];
+        let mut idx = source;
+        loop {
+            points.push(to_xy(idx, width));
+            // Joined a river we already traced, stop at the confluence
+            if visited[idx] {
+                break;
+            }
+            visited[idx] = true;
+
+            match drainage.downstream[idx] {
+                Some(next) if cells[next] == WaterCell::River => idx = next,
+                Some(next) => {
+                    // Into the sea or a lake
+                    points.push(to_xy(next, width));
+                    break;
+                }
+                None => break,
+            }
+        }
+
+        if points.len() > 1 {
+            rivers.push(River {
+                points,
+                flow: drainage.accumulation[idx],
+            });
+        }
+    }
+
+    rivers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZE: usize = 48;
+
+    // Sea along the left edge, ground rising to the right with a valley down the
+    // middle, so water has somewhere obvious to go
+    fn valley() -> NoiseMap {
+        let mut map = NoiseMap::new(SIZE, SIZE);
+        for y in 0..SIZE {
+            for x in 0..SIZE {
+                let height = if x < 3 {
+                    0.02
+                } else {
+                    0.1 + 0.012 * x as f64 + 0.01 * (y as f64 - SIZE as f64 / 2.0).abs()
+                };
+                map.set_value(x, y, height);
+            }
+        }
+        map
+    }
+
+    #[test]
+    fn rivers_flow_downhill_to_the_sea() {
+        let (map, rivers) = simulate_rainfall_river_generation_erosion(valley(), 0, 1.0);
+        assert!(!rivers.rivers.is_empty());
+
+        for river in rivers.rivers.iter() {
+            for pair in river.points.windows(2) {
+                let upstream = map.get_value(pair[0].0 as usize, pair[0].1 as usize);
+                let downstream = map.get_value(pair[1].0 as usize, pair[1].1 as usize);
+                assert!(downstream <= upstream, "{:?} flows uphill", pair);
+            }
+        }
+
+        assert!(rivers.rivers.iter().any(|river| {
+            let (x, y) = *river.points.last().unwrap();
+            map.get_value(x as usize, y as usize) < SEA_LEVEL
+        }));
+    }
+
+    #[test]
+    fn pits_fill_into_lakes() {
+        let mut map = valley();
+        let floor = map.get_value(30, 10) - 0.1;
+        for y in 9..=11 {
+            for x in 29..=31 {
+                map.set_value(x, y, floor);
+            }
+        }
+
+        let (_, rivers) = simulate_rainfall_river_generation_erosion(map, 0, 1.0);
+        assert!(rivers.is_lake(30, 10));
+        assert!(rivers
+            .lakes
+            .iter()
+            .any(|lake| lake.cells.contains(&(30, 10))));
+        assert!(!rivers.is_water(40, 40));
+    }
+
+    #[test]
+    fn erosion_only_lowers_the_ground() {
+        let before = valley();
+        let (after, _) = simulate_rainfall_river_generation_erosion(valley(), 3, 1.0);
+
+        let mut lowered = false;
+        for (old, new) in before.iter().zip(after.iter()) {
+            assert!(new <= old);
+            lowered |= new < old;
+        }
+        assert!(lowered);
+    }
+}
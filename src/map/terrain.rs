@@ -0,0 +1,221 @@
+use bevy::prelude::*;
+use bevy_ecs_tilemap::prelude::*;
+
+use crate::*;
+
+// Send this to change the ground, the noise map, biome, rivers and rendered tiles
+// are all updated together. TerrainChanged goes out afterwards for anything that
+// caches terrain (minimap, pathfinding, ...)
+#[derive(Event, Debug, Clone, Copy)]
+pub struct TerrainEdit {
+    pub tile: TilePos,
+    pub change: TerrainChange,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum TerrainChange {
+    // Biome is reclassified from the new height, with the tile's own climate
+    SetElevation(f64),
+    // Pits and mounds
+    RaiseElevation(f64),
+    // Keeps the height, for things like bridges or freezing water
+    SetBiome(Biome),
+}
+
+#[derive(Event, Debug, Clone, Copy)]
+pub struct TerrainChanged {
+    pub tile: TilePos,
+}
+
+pub struct TerrainPlugin<S: States> {
+    pub state: S,
+}
+
+impl<S: States> Plugin for TerrainPlugin<S> {
+    fn build(&self, app: &mut App) {
+        app.add_event::<TerrainEdit>()
+            .add_event::<TerrainChanged>()
+            .add_systems(
+                Update,
+                apply_terrain_edits
+                    .run_if(on_event::<TerrainEdit>())
+                    .run_if(in_state(self.state.clone())),
+            );
+    }
+}
+
+// No ECS needed, so tools and tests can edit a GameState directly. Returns false
+// if the tile is off the map.
+pub fn apply_terrain_edit(state: &mut GameState, edit: &TerrainEdit) -> bool {
+    let (width, height) = state.map.size();
+    let TilePos { x, y } = edit.tile;
+    if x as usize >= width || y as usize >= height {
+        return false;
+    }
+
+    let elevation = state.map.get_value(x as usize, y as usize);
+
+    match edit.change {
+        TerrainChange::SetElevation(new_elevation) => {
+            set_elevation(state, x, y, new_elevation);
+        }
+        TerrainChange::RaiseElevation(delta) => {
+            set_elevation(state, x, y, elevation + delta);
+        }
+        TerrainChange::SetBiome(biome) => {
+            // A bridge over a river is dry ground, flooding makes it a lake
+            let cell = match state.rivers.get(x, y) {
+                _ if !biome.is_water() => WaterCell::Dry,
+                WaterCell::Dry if elevation >= SEA_LEVEL => WaterCell::Lake,
+                cell => cell,
+            };
+            state.rivers.set(x, y, cell);
+            state.biomes.set(x, y, biome);
+        }
+    }
+
+    true
+}
+
+fn set_elevation(state: &mut GameState, x: u32, y: u32, elevation: f64) {
+    let elevation = elevation.clamp(0.0, 1.0);
+    state.map.set_value(x as usize, y as usize, elevation);
+
+    // Raised above the water line fills in rivers and lakes
+    if elevation >= SEA_LEVEL {
+        state.rivers.set(x, y, WaterCell::Dry);
+    }
+
+    if state.rivers.is_water(x, y) {
+        state.biomes.set(x, y, Biome::ShallowWater);
+    } else {
+        state.biomes.reclassify(x, y, elevation);
+    }
+}
+
+fn apply_terrain_edits(
+    mut ev_edits: EventReader<TerrainEdit>,
+    mut ev_changed: EventWriter<TerrainChanged>,
+    mut state: ResMut<GameState>,
+    map_tiles: MapTiles,
+    mut tiles: Query<&mut TileTextureIndex>,
+) {
+    for edit in ev_edits.read() {
+        if !apply_terrain_edit(&mut state, edit) {
+            log::warn!("Terrain edit off the map: {:?}", edit);
+            continue;
+        }
+
+        // Neighbours may need a different coast or mountain edge now
+        retile_around(&map_tiles, &state.biomes, &edit.tile, &mut tiles);
+
+        ev_changed.send(TerrainChanged { tile: edit.tile });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+    use crate::map::fixtures::*;
+
+    fn edit(x: u32, y: u32, change: TerrainChange) -> TerrainEdit {
+        TerrainEdit {
+            tile: TilePos { x, y },
+            change,
+        }
+    }
+
+    #[test]
+    fn raising_makes_mountains() {
+        let mut state = flat_state(10, 0.3);
+        assert!(apply_terrain_edit(
+            &mut state,
+            &edit(4, 4, TerrainChange::RaiseElevation(0.5))
+        ));
+
+        assert!((state.map.get_value(4, 4) - 0.8).abs() < 1e-9);
+        assert!(matches!(
+            state.biomes.get(4, 4),
+            Biome::Mountain | Biome::Snow
+        ));
+        // Only the one tile
+        assert_eq!(state.map.get_value(5, 4), 0.3);
+    }
+
+    #[test]
+    fn lowering_floods() {
+        let mut state = flat_state(10, 0.3);
+        apply_terrain_edit(&mut state, &edit(4, 4, TerrainChange::SetElevation(0.0)));
+        assert_eq!(state.map.get_value(4, 4), 0.0);
+        assert!(state.biomes.get(4, 4).is_water());
+
+        // And back up again
+        apply_terrain_edit(&mut state, &edit(4, 4, TerrainChange::SetElevation(0.3)));
+        assert!(!state.biomes.get(4, 4).is_water());
+    }
+
+    #[test]
+    fn elevation_is_clamped() {
+        let mut state = flat_state(10, 0.3);
+        apply_terrain_edit(&mut state, &edit(1, 1, TerrainChange::RaiseElevation(2.0)));
+        assert_eq!(state.map.get_value(1, 1), 1.0);
+
+        apply_terrain_edit(&mut state, &edit(1, 1, TerrainChange::RaiseElevation(-5.0)));
+        assert_eq!(state.map.get_value(1, 1), 0.0);
+    }
+
+    #[test]
+    fn set_biome_keeps_water_in_sync() {
+        let mut state = flat_state(10, 0.3);
+        apply_terrain_edit(
+            &mut state,
+            &edit(2, 3, TerrainChange::SetBiome(Biome::ShallowWater)),
+        );
+        assert!(state.rivers.is_lake(2, 3));
+        assert_eq!(state.map.get_value(2, 3), 0.3);
+
+        apply_terrain_edit(
+            &mut state,
+            &edit(2, 3, TerrainChange::SetBiome(Biome::Grassland)),
+        );
+        assert!(!state.rivers.is_water(2, 3));
+        assert_eq!(state.biomes.get(2, 3), Biome::Grassland);
+    }
+
+    #[test]
+    fn off_the_map_is_rejected() {
+        let mut state = flat_state(10, 0.3);
+        assert!(!apply_terrain_edit(
+            &mut state,
+            &edit(10, 0, TerrainChange::SetElevation(0.9))
+        ));
+        assert!(state.map.iter().all(|val| *val == 0.3));
+    }
+
+    #[test]
+    fn edits_send_terrain_changed() {
+        let mut world = World::new();
+        world.insert_resource(flat_state(10, 0.3));
+        world.insert_resource(MapChunks::new(
+            TilemapSize { x: 10, y: 10 },
+            TilemapTileSize { x: 32.0, y: 32.0 },
+        ));
+        world.init_resource::<Events<TerrainEdit>>();
+        world.init_resource::<Events<TerrainChanged>>();
+
+        world.send_event(edit(3, 3, TerrainChange::SetElevation(0.0)));
+        world.send_event(edit(30, 3, TerrainChange::SetElevation(0.0)));
+        world.run_system_once(apply_terrain_edits);
+
+        let events = world.resource::<Events<TerrainChanged>>();
+        let changed: Vec<TilePos> = events
+            .get_reader()
+            .read(events)
+            .map(|changed| changed.tile)
+            .collect();
+        assert_eq!(changed, vec![TilePos { x: 3, y: 3 }]);
+        assert_eq!(world.resource::<GameState>().map.get_value(3, 3), 0.0);
+    }
+}
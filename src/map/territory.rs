@@ -0,0 +1,534 @@
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+use bevy_ecs_tilemap::prelude::*;
+
+use crate::*;
+
+// Blank tile (tilemap index 11), tinted by owner
+const BORDER_TILE: u32 = 11;
+const PLAYER_BORDER_COLOR: Color = Color::rgba(0.2, 0.5, 1.0, 0.6);
+const ENEMY_BORDER_COLOR: Color = Color::rgba(1.0, 0.15, 0.15, 0.6);
+
+// Tower tiles are drawn as is for the player, tinted for the enemy
+const ENEMY_TOWER_TINT: Color = Color::rgb(1.0, 0.45, 0.45);
+
+// Tiles (diagonals count as one) a tower's own units can be from it and still stop
+// a capture
+const DEFEND_RADIUS: u32 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Faction {
+    Player,
+    Enemy,
+}
+
+impl Faction {
+    // Units are the player's unless they're marked Enemy
+    pub fn of_unit(is_enemy: bool) -> Self {
+        if is_enemy {
+            Faction::Enemy
+        } else {
+            Faction::Player
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TerritoryTower {
+    pub loc: (u32, u32),
+    pub owner: Faction,
+}
+
+// Every land tile belongs to the tower closest to it over land (water isn't
+// crossed, rivers are forded), so a tower across the sea doesn't own the coast
+// opposite. Region 0 is the player tower, then enemy_tower_locations in order.
+#[derive(Resource, Default)]
+pub struct Territories {
+    width: u32,
+    height: u32,
+    regions: Vec<Option<u16>>,
+    pub towers: Vec<TerritoryTower>,
+}
+
+impl Territories {
+    pub fn compute(state: &GameState) -> Self {
+        let towers = std::iter::once(TerritoryTower {
+            loc: state.player_tower_location,
+            owner: Faction::Player,
+        })
+        .chain(
+            state
+                .enemy_tower_locations
+                .iter()
+                .map(|loc| TerritoryTower {
+                    loc: *loc,
+                    owner: Faction::Enemy,
+                }),
+        )
+        .collect();
+
+        let mut territories = Self {
+            towers,
+            ..default()
+        };
+        territories.assign_regions(&state.map, &state.rivers);
+        territories
+    }
+
+    // After the terrain changes, towers keep their owners. Returns whether any
+    // tile changed hands.
+    pub fn recompute(&mut self, state: &GameState) -> bool {
+        let previous = std::mem::take(&mut self.regions);
+        self.assign_regions(&state.map, &state.rivers);
+        previous != self.regions
+    }
+
+    // Multi-source BFS from every tower at once, first to reach a tile keeps it
+    fn assign_regions(&mut self, map: &NoiseMap, rivers: &RiverNetwork) {
+        let (width, height) = map.size();
+        self.width = width as u32;
+        self.height = height as u32;
+        self.regions = vec![None; width * height];
+
+        let mut queue = VecDeque::new();
+        for (region, tower) in self.towers.iter().enumerate() {
+            let (x, y) = tower.loc;
+            let idx = x as usize + y as usize * width;
+            if x < self.width && y < self.height && self.regions[idx].is_none() {
+                self.regions[idx] = Some(region as u16);
+                queue.push_back((x, y));
+            }
+        }
+
+        while let Some((x, y)) = queue.pop_front() {
+            let region = self.regions[x as usize + y as usize * width];
+            for (dx, dy) in [(-1, 0), (1, 0), (0, -1), (0, 1)] {
+                let nx = x as i32 + dx;
+                let ny = y as i32 + dy;
+                if nx < 0 || ny < 0 || nx >= width as i32 || ny >= height as i32 {
+                    continue;
+                }
+                let (nx, ny) = (nx as u32, ny as u32);
+                let idx = nx as usize + ny as usize * width;
+                if self.regions[idx].is_none() && is_land(map, rivers, nx, ny) {
+                    self.regions[idx] = region;
+                    queue.push_back((nx, ny));
+                }
+            }
+        }
+    }
+
+    // Index into towers, None for water or land no tower can reach
+    pub fn region_at(&self, x: u32, y: u32) -> Option<usize> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        self.regions[(x + y * self.width) as usize].map(|region| region as usize)
+    }
+
+    // Who controls this tile?
+    pub fn controller(&self, x: u32, y: u32) -> Option<Faction> {
+        self.region_at(x, y).map(|region| self.towers[region].owner)
+    }
+
+    pub fn controller_at(&self, tile_pos: &TilePos) -> Option<Faction> {
+        self.controller(tile_pos.x, tile_pos.y)
+    }
+
+    // Tiles next to a different region (or unclaimed land / water)
+    pub fn is_border(&self, x: u32, y: u32) -> bool {
+        let Some(region) = self.region_at(x, y) else {
+            return false;
+        };
+
+        [(-1, 0), (1, 0), (0, -1), (0, 1)].iter().any(|(dx, dy)| {
+            let (nx, ny) = (x as i32 + dx, y as i32 + dy);
+            nx < 0 || ny < 0 || self.region_at(nx as u32, ny as u32) != Some(region)
+        })
+    }
+
+    // Returns false if there's no tower there or it already belongs to them
+    pub fn capture(&mut self, loc: (u32, u32), by: Faction) -> bool {
+        match self.towers.iter_mut().find(|tower| tower.loc == loc) {
+            Some(tower) if tower.owner != by => {
+                tower.owner = by;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+// Send to hand a tower (and its whole territory) to someone else
+#[derive(Event, Debug, Clone, Copy)]
+pub struct CaptureTower {
+    pub loc: (u32, u32),
+    pub by: Faction,
+}
+
+#[derive(Event, Debug, Clone, Copy)]
+pub struct TerritoryChanged {
+    pub region: usize,
+}
+
+#[derive(Component)]
+pub struct TerritoryOverlay;
+
+// Whether the overlay is toggled on, so chunks streamed in later match
+#[derive(Resource, Default)]
+pub struct ShowTerritories(pub bool);
+
+pub struct TerritoryPlugin<S: States> {
+    pub state: S,
+}
+
+impl<S: States> Plugin for TerritoryPlugin<S> {
+    fn build(&self, app: &mut App) {
+        app.add_event::<CaptureTower>()
+            .add_event::<TerritoryChanged>()
+            .init_resource::<ShowTerritories>()
+            .add_systems(
+                Update,
+                (
+                    recompute_territories.run_if(on_event::<TerrainChanged>()),
+                    capture_occupied_towers,
+                    capture_towers.run_if(on_event::<CaptureTower>()),
+                    (recolor_territory_overlay, recolor_towers)
+                        .run_if(on_event::<TerritoryChanged>()),
+                    toggle_territory_overlay,
+                )
+                    .chain()
+                    .run_if(in_state(self.state.clone())),
+            );
+    }
+}
+
+pub fn compute_territories(mut commands: Commands, state: Res<GameState>) {
+    commands.insert_resource(Territories::compute(&state));
+}
+
+fn border_color(owner: Faction) -> Color {
+    match owner {
+        Faction::Player => PLAYER_BORDER_COLOR,
+        Faction::Enemy => ENEMY_BORDER_COLOR,
+    }
+}
+
+// Borders only, on their own sparse layer between the ground and the map stuff.
+// Hidden until toggled with T.
+pub fn spawn_territory_chunk(
+    commands: &mut Commands,
+    chunks: &MapChunks,
+    chunk: UVec2,
+    territories: &Territories,
+    assets: &GameAssets,
+    show: bool,
+) -> Entity {
+    let size = chunks.chunk_size(chunk);
+    let offset = chunk * CHUNK_SIZE;
+
+    let overlay_entity = commands
+        .spawn((TerritoryOverlay, MapChunk { pos: chunk }))
+        .id();
+    let mut storage = TileStorage::empty(size);
+
+    for y in 0..size.y {
+        for x in 0..size.x {
+            let (global_x, global_y) = (offset.x + x, offset.y + y);
+            if !territories.is_border(global_x, global_y) {
+                continue;
+            }
+            let owner = territories.controller(global_x, global_y).unwrap();
+
+            let tile_pos = TilePos { x, y };
+            let tile_entity = commands
+                .spawn(TileBundle {
+                    position: tile_pos,
+                    tilemap_id: TilemapId(overlay_entity),
+                    texture_index: TileTextureIndex(BORDER_TILE),
+                    color: TileColor(border_color(owner)),
+                    ..Default::default()
+                })
+                .id();
+            storage.set(&tile_pos, tile_entity);
+        }
+    }
+
+    commands.entity(overlay_entity).insert((
+        TilemapBundle {
+            grid_size: chunks.grid_size,
+            map_type: chunks.map_type,
+            size,
+            storage,
+            texture: TilemapTexture::Single(assets.tiles.clone()),
+            tile_size: chunks.tile_size,
+            transform: chunks.chunk_transform(chunk, 0.5),
+            visibility: overlay_visibility(show),
+            ..Default::default()
+        },
+        Name::from(format!("TerritoryChunk {} {}", chunk.x, chunk.y)),
+    ));
+
+    overlay_entity
+}
+
+pub fn tower_color(owner: Faction) -> Color {
+    match owner {
+        Faction::Player => Color::WHITE,
+        Faction::Enemy => ENEMY_TOWER_TINT,
+    }
+}
+
+fn overlay_visibility(show: bool) -> Visibility {
+    if show {
+        Visibility::Inherited
+    } else {
+        Visibility::Hidden
+    }
+}
+
+// Digging and flooding can open or cut off land, borders get rebuilt as chunks
+// stream back in
+fn recompute_territories(
+    mut commands: Commands,
+    mut ev_terrain: EventReader<TerrainChanged>,
+    mut territories: ResMut<Territories>,
+    mut chunks: ResMut<MapChunks>,
+    storage_q: Query<&TileStorage, (With<MapChunk>, Without<MapStuff>)>,
+    state: Res<GameState>,
+) {
+    ev_terrain.clear();
+
+    if !territories.recompute(&state) {
+        return;
+    }
+
+    for overlay in chunks.take_overlay() {
+        despawn_chunk(&mut commands, &storage_q, overlay);
+    }
+}
+
+fn can_defend(a: &TilePos, b: &TilePos) -> bool {
+    a.x.abs_diff(b.x).max(a.y.abs_diff(b.y)) <= DEFEND_RADIUS
+}
+
+// Standing on someone else's tower with none of its defenders close by takes it
+fn capture_occupied_towers(
+    units: Query<(&TilePos, Has<Enemy>), With<Unit>>,
+    territories: Res<Territories>,
+    mut ev_capture: EventWriter<CaptureTower>,
+) {
+    for (tile_pos, is_enemy) in units.iter() {
+        let by = Faction::of_unit(is_enemy);
+        let Some(tower) = territories
+            .towers
+            .iter()
+            .find(|tower| tower.loc == (tile_pos.x, tile_pos.y) && tower.owner != by)
+        else {
+            continue;
+        };
+
+        let defended = units.iter().any(|(other_pos, other_enemy)| {
+            Faction::of_unit(other_enemy) == tower.owner && can_defend(tile_pos, other_pos)
+        });
+        if !defended {
+            ev_capture.send(CaptureTower { loc: tower.loc, by });
+        }
+    }
+}
+
+fn capture_towers(
+    mut ev_capture: EventReader<CaptureTower>,
+    mut ev_changed: EventWriter<TerritoryChanged>,
+    mut territories: ResMut<Territories>,
+) {
+    for CaptureTower { loc, by } in ev_capture.read() {
+        if territories.capture(*loc, *by) {
+            log::info!("Tower at {:?} captured by {:?}", loc, by);
+            let region = territories
+                .towers
+                .iter()
+                .position(|tower| tower.loc == *loc)
+                .unwrap();
+            ev_changed.send(TerritoryChanged { region });
+        }
+    }
+}
+
+fn recolor_territory_overlay(
+    mut ev_changed: EventReader<TerritoryChanged>,
+    territories: Res<Territories>,
+    overlay_q: Query<(&MapChunk, &TileStorage), With<TerritoryOverlay>>,
+    mut tile_q: Query<(&TilePos, &mut TileColor)>,
+) {
+    let regions: Vec<usize> = ev_changed.read().map(|ev| ev.region).collect();
+
+    // Only the loaded chunks, the rest are built with the right colours
+    for (chunk, storage) in overlay_q.iter() {
+        let offset = chunk.pos * CHUNK_SIZE;
+        for tile_entity in storage.iter().flatten() {
+            let Ok((tile_pos, mut color)) = tile_q.get_mut(*tile_entity) else {
+                continue;
+            };
+
+            let (x, y) = (offset.x + tile_pos.x, offset.y + tile_pos.y);
+            if let Some(region) = territories.region_at(x, y) {
+                if regions.contains(&region) {
+                    color.0 = border_color(territories.towers[region].owner);
+                }
+            }
+        }
+    }
+}
+
+fn recolor_towers(
+    territories: Res<Territories>,
+    mut tower_q: Query<(&TilePos, &mut TileColor), With<TowerTile>>,
+) {
+    for (tile_pos, mut color) in tower_q.iter_mut() {
+        if let Some(owner) = territories.controller_at(tile_pos) {
+            color.0 = tower_color(owner);
+        }
+    }
+}
+
+fn toggle_territory_overlay(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut show_territories: ResMut<ShowTerritories>,
+    mut overlay_q: Query<&mut Visibility, With<TerritoryOverlay>>,
+) {
+    if !keyboard_input.just_pressed(KeyCode::KeyT) {
+        return;
+    }
+
+    show_territories.0 = !show_territories.0;
+    for mut visibility in overlay_q.iter_mut() {
+        *visibility = overlay_visibility(show_territories.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+
+    // 20x10 of land, split by water at x = 10 and x = 15
+    fn state() -> GameState {
+        let mut map = NoiseMap::new(20, 10);
+        for y in 0..10 {
+            for x in 0..20 {
+                let water = x == 10 || x == 15;
+                map.set_value(x, y, if water { 0.0 } else { 0.3 });
+            }
+        }
+
+        GameState {
+            map,
+            player_tower_location: (2, 5),
+            enemy_tower_locations: vec![(12, 5)],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn towers_own_the_land_they_reach() {
+        let territories = Territories::compute(&state());
+        assert_eq!(territories.towers.len(), 2);
+
+        assert_eq!(territories.region_at(0, 0), Some(0));
+        assert_eq!(territories.region_at(9, 9), Some(0));
+        assert_eq!(territories.controller(5, 5), Some(Faction::Player));
+        assert_eq!(territories.region_at(11, 0), Some(1));
+        assert_eq!(territories.controller(14, 9), Some(Faction::Enemy));
+
+        // Water, the island past it and off the map belong to nobody
+        assert_eq!(territories.region_at(10, 5), None);
+        assert_eq!(territories.region_at(17, 5), None);
+        assert_eq!(territories.region_at(20, 5), None);
+    }
+
+    #[test]
+    fn closest_tower_over_land_wins() {
+        let mut state = state();
+        for y in 0..10 {
+            state.map.set_value(10, y, 0.3);
+        }
+        state.enemy_tower_locations = vec![(14, 5)];
+
+        let territories = Territories::compute(&state);
+        assert_eq!(territories.region_at(7, 5), Some(0));
+        assert_eq!(territories.region_at(9, 5), Some(1));
+        assert!(territories.is_border(8, 5));
+        assert!(territories.is_border(9, 5));
+        assert!(!territories.is_border(5, 5));
+    }
+
+    #[test]
+    fn borders_are_next_to_anything_else() {
+        let territories = Territories::compute(&state());
+        assert!(territories.is_border(9, 5));
+        assert!(territories.is_border(0, 5));
+        assert!(territories.is_border(5, 9));
+        assert!(!territories.is_border(5, 5));
+        assert!(!territories.is_border(10, 5));
+    }
+
+    #[test]
+    fn capturing_keeps_the_region() {
+        let mut territories = Territories::compute(&state());
+        assert!(territories.capture((12, 5), Faction::Player));
+        assert!(!territories.capture((12, 5), Faction::Player));
+        assert!(!territories.capture((13, 5), Faction::Enemy));
+        assert_eq!(territories.controller(14, 9), Some(Faction::Player));
+        assert_eq!(territories.region_at(14, 9), Some(1));
+    }
+
+    #[test]
+    fn recompute_keeps_owners() {
+        let mut state = state();
+        let mut territories = Territories::compute(&state);
+        territories.capture((12, 5), Faction::Player);
+        assert!(!territories.recompute(&state));
+
+        // Bridge over to the island
+        state.map.set_value(15, 5, 0.3);
+        assert!(territories.recompute(&state));
+        assert_eq!(territories.region_at(17, 5), Some(1));
+        assert_eq!(territories.controller(17, 5), Some(Faction::Player));
+    }
+
+    // (x, y, is_enemy) for each unit, gives the towers they capture
+    fn captures(units: &[(u32, u32, bool)]) -> Vec<(u32, u32)> {
+        let mut world = World::new();
+        world.insert_resource(Territories::compute(&state()));
+        world.init_resource::<Events<CaptureTower>>();
+        for (x, y, enemy) in units {
+            let mut unit = world.spawn((Unit::scout(), TilePos { x: *x, y: *y }));
+            if *enemy {
+                unit.insert(Enemy);
+            }
+        }
+        world.run_system_once(capture_occupied_towers);
+
+        let events = world.resource::<Events<CaptureTower>>();
+        events
+            .get_reader()
+            .read(events)
+            .map(|capture| capture.loc)
+            .collect()
+    }
+
+    #[test]
+    fn undefended_towers_are_captured() {
+        assert_eq!(captures(&[(12, 5, false)]), vec![(12, 5)]);
+
+        // A defender close enough, then one just too far away
+        assert!(captures(&[(12, 5, false), (14, 7, true)]).is_empty());
+        assert_eq!(captures(&[(12, 5, false), (14, 8, true)]), vec![(12, 5)]);
+
+        // Nobody captures their own tower
+        assert!(captures(&[(2, 5, false), (12, 5, true)]).is_empty());
+    }
+}
@@ -0,0 +1,422 @@
+use std::collections::VecDeque;
+
+use bevy::math::Vec2;
+use noise::utils::NoiseMap;
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+use crate::*;
+
+// Candidates tried around each point before it's retired (Bridson's k)
+const POISSON_ATTEMPTS: usize = 30;
+
+// Times the fallback halves the spacing rules before settling for what it has
+const FALLBACK_ATTEMPTS: usize = 4;
+
+#[derive(Debug, Clone)]
+pub struct TowerPlacement {
+    pub player: (u32, u32),
+    pub enemies: Vec<(u32, u32)>,
+}
+
+#[derive(Debug, Clone)]
+pub enum TowerPlacementError {
+    NoPlayerSite,
+    NotEnoughEnemySites { wanted: u32, found: u32 },
+}
+
+impl std::fmt::Display for TowerPlacementError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TowerPlacementError::NoPlayerSite => {
+                write!(f, "No valid site for the player tower")
+            }
+            TowerPlacementError::NotEnoughEnemySites { wanted, found } => write!(
+                f,
+                "Only {} of {} enemy towers could be placed",
+                found, wanted
+            ),
+        }
+    }
+}
+
+impl std::error::Error for TowerPlacementError {}
+
+// Towers are Poisson-disk sampled so they keep min_tower_spacing apart, enemies
+// also have to be far enough from the player and reachable over land
+pub fn place_towers<R: Rng + ?Sized>(
+    map: &NoiseMap,
+    rivers: &RiverNetwork,
+    rng: &mut R,
+    preset: &WorldGenPreset,
+) -> Result<TowerPlacement, TowerPlacementError> {
+    let wanted = rng.gen_range(preset_range(preset.enemy_towers));
+
+    let (width, height) = map.size();
+    let points = poisson_disk(
+        width as u32,
+        height as u32,
+        preset.min_tower_spacing as f32,
+        rng,
+    );
+
+    let player = player_site(&points, map, rivers, rng).ok_or(TowerPlacementError::NoPlayerSite)?;
+
+    let mut enemies = enemy_sites(
+        &points,
+        player,
+        map,
+        rivers,
+        preset.min_player_distance as f32,
+        rng,
+    );
+
+    if (enemies.len() as u32) < wanted {
+        return Err(TowerPlacementError::NotEnoughEnemySites {
+            wanted,
+            found: enemies.len() as u32,
+        });
+    }
+    enemies.truncate(wanted as usize);
+
+    Ok(TowerPlacement { player, enemies })
+}
+
+// When place_towers can't satisfy the preset, relax the spacing rules a few times
+// and keep the best attempt
+pub fn fallback_towers<R: Rng + ?Sized>(
+    map: &NoiseMap,
+    rivers: &RiverNetwork,
+    rng: &mut R,
+    preset: &WorldGenPreset,
+) -> TowerPlacement {
+    let wanted = rng.gen_range(preset_range(preset.enemy_towers)) as usize;
+
+    let (width, height) = map.size();
+    let mut spacing = preset.min_tower_spacing as f32;
+    let mut player_distance = preset.min_player_distance as f32;
+    let mut best: Option<TowerPlacement> = None;
+
+    for _ in 0..FALLBACK_ATTEMPTS {
+        spacing = (spacing / 2.0).max(2.0);
+        player_distance /= 2.0;
+
+        let points = poisson_disk(width as u32, height as u32, spacing, rng);
+        let player = player_site(&points, map, rivers, rng)
+            .unwrap_or_else(|| nearest_land_to_center(map, rivers));
+
+        let mut enemies = enemy_sites(&points, player, map, rivers, player_distance, rng);
+        enemies.truncate(wanted);
+
+        if enemies.len() == wanted {
+            return TowerPlacement { player, enemies };
+        }
+
+        if best
+            .as_ref()
+            .map_or(true, |best| enemies.len() > best.enemies.len())
+        {
+            best = Some(TowerPlacement { player, enemies });
+        }
+    }
+
+    best.expect("At least one fallback attempt")
+}
+
+pub(crate) fn is_land(map: &NoiseMap, rivers: &RiverNetwork, x: u32, y: u32) -> bool {
+    // Rivers can be forded, lakes and the sea can't
+    map.get_value(x as usize, y as usize) >= SEA_LEVEL && !rivers.is_lake(x, y)
+}
+
+fn player_site<R: Rng + ?Sized>(
+    points: &[(u32, u32)],
+    map: &NoiseMap,
+    rivers: &RiverNetwork,
+    rng: &mut R,
+) -> Option<(u32, u32)> {
+    let (width, height) = map.size();
+
+    // Player tower stays within the middle 60% of the map (so not the edge of the map)
+    let player_x = fraction_range(width as u32, 0.2, 0.8);
+    let player_y = fraction_range(height as u32, 0.2, 0.8);
+
+    let sites: Vec<(u32, u32)> = points
+        .iter()
+        .filter(|(x, y)| {
+            // Between 0.1 and 0.7
+            let val = map.get_value(*x as usize, *y as usize);
+            player_x.contains(x)
+                && player_y.contains(y)
+                && val > 0.1
+                && val < 0.7
+                && !rivers.is_water(*x, *y)
+        })
+        .copied()
+        .collect();
+
+    sites.choose(rng).copied()
+}
+
+fn enemy_sites<R: Rng + ?Sized>(
+    points: &[(u32, u32)],
+    player: (u32, u32),
+    map: &NoiseMap,
+    rivers: &RiverNetwork,
+    player_distance: f32,
+    rng: &mut R,
+) -> Vec<(u32, u32)> {
+    let (width, height) = map.size();
+
+    // Enemy towers can go a bit further out, middle 80%
+    let enemy_x = fraction_range(width as u32, 0.1, 0.9);
+    let enemy_y = fraction_range(height as u32, 0.1, 0.9);
+
+    let reachable = land_reachable_from(map, rivers, player);
+    let player_pos = Vec2::new(player.0 as f32, player.1 as f32);
+
+    let mut sites: Vec<(u32, u32)> = points
+        .iter()
+        .filter(|(x, y)| {
+            (*x, *y) != player
+                && enemy_x.contains(x)
+                && enemy_y.contains(y)
+                && map.get_value(*x as usize, *y as usize) > 0.1
+                && !rivers.is_water(*x, *y)
+                && reachable[*x as usize + *y as usize * width]
+                && Vec2::new(*x as f32, *y as f32).distance(player_pos) >= player_distance
+        })
+        .copied()
+        .collect();
+
+    sites.shuffle(rng);
+    sites
+}
+
+// Flood fill over land (4-neighbour) from the start tile
+pub fn land_reachable_from(map: &NoiseMap, rivers: &RiverNetwork, start: (u32, u32)) -> Vec<bool> {
+    let (width, height) = map.size();
+    let mut reachable = vec![false; width * height];
+
+    if !is_land(map, rivers, start.0, start.1) {
+        return reachable;
+    }
+
+    let mut queue = VecDeque::new();
+    reachable[start.0 as usize + start.1 as usize * width] = true;
+    queue.push_back(start);
+
+    while let Some((x, y)) = queue.pop_front() {
+        for (dx, dy) in [(-1, 0), (1, 0), (0, -1), (0, 1)] {
+            let nx = x as i32 + dx;
+            let ny = y as i32 + dy;
+            if nx < 0 || ny < 0 || nx >= width as i32 || ny >= height as i32 {
+                continue;
+            }
+            let (nx, ny) = (nx as u32, ny as u32);
+            let idx = nx as usize + ny as usize * width;
+            if !reachable[idx] && is_land(map, rivers, nx, ny) {
+                reachable[idx] = true;
+                queue.push_back((nx, ny));
+            }
+        }
+    }
+
+    reachable
+}
+
+// Last resort for the player tower, closest usable land to the middle of the map
+fn nearest_land_to_center(map: &NoiseMap, rivers: &RiverNetwork) -> (u32, u32) {
+    let (width, height) = map.size();
+    let center = Vec2::new(width as f32 / 2.0, height as f32 / 2.0);
+
+    let mut best: Option<((u32, u32), f32)> = None;
+    for y in 0..height as u32 {
+        for x in 0..width as u32 {
+            if !is_land(map, rivers, x, y) {
+                continue;
+            }
+            let distance = Vec2::new(x as f32, y as f32).distance_squared(center);
+            if best.map_or(true, |(_, best_distance)| distance < best_distance) {
+                best = Some(((x, y), distance));
+            }
+        }
+    }
+
+    best.map(|(loc, _)| loc)
+        .unwrap_or((width as u32 / 2, height as u32 / 2))
+}
+
+// Bridson's Poisson-disk sampling, every point is at least radius from the others
+fn poisson_disk<R: Rng + ?Sized>(
+    width: u32,
+    height: u32,
+    radius: f32,
+    rng: &mut R,
+) -> Vec<(u32, u32)> {
+    let radius = radius.max(1.0);
+    let cell_size = radius / std::f32::consts::SQRT_2;
+    let grid_width = (width as f32 / cell_size).ceil() as usize + 1;
+    let grid_height = (height as f32 / cell_size).ceil() as usize + 1;
+
+    let mut grid: Vec<Option<usize>> = vec![None; grid_width * grid_height];
+    let mut points: Vec<Vec2> = vec![];
+    let mut active: Vec<usize> = vec![];
+
+    let grid_idx = |p: Vec2| (p.x / cell_size) as usize + (p.y / cell_size) as usize * grid_width;
+
+    let first = Vec2::new(
+        rng.gen_range(0.0..width as f32),
+        rng.gen_range(0.0..height as f32),
+    );
+    grid[grid_idx(first)] = Some(0);
+    points.push(first);
+    active.push(0);
+
+    while !active.is_empty() {
+        let active_idx = rng.gen_range(0..active.len());
+        let origin = points[active[active_idx]];
+        let mut found = false;
+
+        for _ in 0..POISSON_ATTEMPTS {
+            let angle = rng.gen_range(0.0..std::f32::consts::TAU);
+            let distance = rng.gen_range(radius..radius * 2.0);
+            let candidate = origin + Vec2::new(angle.cos(), angle.sin()) * distance;
+
+            if candidate.x < 0.0
+                || candidate.y < 0.0
+                || candidate.x >= width as f32
+                || candidate.y >= height as f32
+            {
+                continue;
+            }
+
+            let gx = (candidate.x / cell_size) as usize;
+            let gy = (candidate.y / cell_size) as usize;
+
+            let mut too_close = false;
+            'neighbours: for nx in gx.saturating_sub(2)..=(gx + 2).min(grid_width - 1) {
+                for ny in gy.saturating_sub(2)..=(gy + 2).min(grid_height - 1) {
+                    if let Some(other) = grid[nx + ny * grid_width] {
+                        if points[other].distance_squared(candidate) < radius * radius {
+                            too_close = true;
+                            break 'neighbours;
+                        }
+                    }
+                }
+            }
+
+            if !too_close {
+                grid[grid_idx(candidate)] = Some(points.len());
+                active.push(points.len());
+                points.push(candidate);
+                found = true;
+                break;
+            }
+        }
+
+        if !found {
+            active.swap_remove(active_idx);
+        }
+    }
+
+    points
+        .into_iter()
+        .map(|p| (p.x as u32, p.y as u32))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::map::fixtures::*;
+
+    // Points are rounded down to whole tiles, which can pull two of them up to a
+    // diagonal closer than they were sampled
+    const ROUNDING: f32 = std::f32::consts::SQRT_2;
+
+    fn distance(a: (u32, u32), b: (u32, u32)) -> f32 {
+        Vec2::new(a.0 as f32, a.1 as f32).distance(Vec2::new(b.0 as f32, b.1 as f32))
+    }
+
+    fn preset() -> WorldGenPreset {
+        WorldGenPreset {
+            enemy_towers: (3, 4),
+            min_tower_spacing: 20,
+            min_player_distance: 40,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn poisson_disk_keeps_its_spacing() {
+        let mut rng = StdRng::seed_from_u64(8);
+        let points = poisson_disk(200, 150, 12.0, &mut rng);
+        assert!(points.len() > 50);
+
+        for (i, a) in points.iter().enumerate() {
+            assert!(a.0 < 200 && a.1 < 150);
+            for b in points[i + 1..].iter() {
+                assert!(distance(*a, *b) >= 12.0 - ROUNDING, "{:?} {:?}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn towers_respect_the_preset_spacing() {
+        let map = flat_map(200, 0.5);
+        let rivers = RiverNetwork::default();
+        let preset = preset();
+
+        for seed in 0..5 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let towers = place_towers(&map, &rivers, &mut rng, &preset).unwrap();
+            assert_eq!(towers.enemies.len(), 3);
+
+            let all: Vec<(u32, u32)> = std::iter::once(towers.player)
+                .chain(towers.enemies.iter().copied())
+                .collect();
+            for (i, a) in all.iter().enumerate() {
+                for b in all[i + 1..].iter() {
+                    assert!(distance(*a, *b) >= 20.0 - ROUNDING);
+                }
+            }
+
+            for enemy in towers.enemies.iter() {
+                assert!(distance(*enemy, towers.player) >= 40.0);
+            }
+        }
+    }
+
+    #[test]
+    fn no_land_is_an_error() {
+        let map = flat_map(100, 0.0);
+        let mut rng = StdRng::seed_from_u64(1);
+        let result = place_towers(&map, &RiverNetwork::default(), &mut rng, &preset());
+        assert!(matches!(result, Err(TowerPlacementError::NoPlayerSite)));
+    }
+
+    #[test]
+    fn fallback_always_finishes() {
+        let mut rng = StdRng::seed_from_u64(1);
+
+        // Nowhere to stand at all
+        let map = flat_map(100, 0.0);
+        let towers = fallback_towers(&map, &RiverNetwork::default(), &mut rng, &preset());
+        assert_eq!(towers.player, (50, 50));
+        assert!(towers.enemies.is_empty());
+
+        // Far more towers than fit, keeps what it could place
+        let crowded = WorldGenPreset {
+            enemy_towers: (500, 501),
+            min_tower_spacing: 60,
+            ..preset()
+        };
+        let map = flat_map(64, 0.5);
+        let towers = fallback_towers(&map, &RiverNetwork::default(), &mut rng, &crowded);
+        assert!(!towers.enemies.is_empty());
+        assert!(towers.enemies.len() < 500);
+    }
+}
@@ -0,0 +1,571 @@
+use std::collections::HashSet;
+
+use bevy::math::Vec2;
+use noise::utils::{NoiseMap, NoiseMapBuilder, PlaneMapBuilder};
+use noise::{Fbm, MultiFractal, Value};
+use rand::Rng;
+use serde::Serialize;
+use xxhash_rust::xxh3::xxh3_64;
+
+use crate::*;
+
+// Tries per treasure spot before giving up on filling the preset's count
+const TREASURE_ATTEMPTS_PER_SPOT: usize = 20;
+
+// Everything needed to build a world, no ECS involved
+#[derive(Debug, Clone)]
+pub struct WorldGenParams {
+    // Root seed, see RngStream
+    pub seed: u64,
+    pub width: u32,
+    pub height: u32,
+    pub preset: WorldGenPreset,
+}
+
+impl WorldGenParams {
+    pub fn from_config(config: &GameConfig) -> Self {
+        let preset = match WorldGenPreset::load(&config.preset) {
+            Ok(preset) => preset,
+            Err(e) => {
+                log::warn!("{}, using the default preset", e);
+                WorldGenPreset::default()
+            }
+        };
+
+        Self {
+            seed: config.rng_seed(),
+            width: config.map_width,
+            height: config.map_height,
+            preset,
+        }
+    }
+}
+
+pub struct GeneratedWorld {
+    pub map: NoiseMap,
+    pub biomes: BiomeMap,
+    pub rivers: RiverNetwork,
+    pub player_tower: (u32, u32),
+    pub enemy_towers: Vec<(u32, u32)>,
+    // Set when the preset's tower constraints couldn't be met and the fallback was used
+    pub tower_error: Option<TowerPlacementError>,
+    pub quality: MapQualityReport,
+    pub treasure_spots: Vec<(u32, u32)>,
+    pub treasures: Vec<Treasure>,
+    pub deposits: Vec<Deposit>,
+}
+
+impl GeneratedWorld {
+    // Stable hash of everything generated, handy for pinning seeds
+    pub fn fingerprint(&self) -> u64 {
+        let mut bytes: Vec<u8> = Vec::with_capacity(self.map.iter().len() * 8);
+        for val in self.map.iter() {
+            bytes.extend_from_slice(&val.to_le_bytes());
+        }
+
+        let (width, height) = self.biomes.size();
+        for y in 0..height as u32 {
+            for x in 0..width as u32 {
+                bytes.push(self.biomes.get(x, y).tile_index() as u8);
+            }
+        }
+
+        let push_loc = |bytes: &mut Vec<u8>, (x, y): (u32, u32)| {
+            bytes.extend_from_slice(&x.to_le_bytes());
+            bytes.extend_from_slice(&y.to_le_bytes());
+        };
+
+        push_loc(&mut bytes, self.player_tower);
+        for loc in self.enemy_towers.iter() {
+            push_loc(&mut bytes, *loc);
+        }
+        for loc in self.treasure_spots.iter() {
+            push_loc(&mut bytes, *loc);
+        }
+        for treasure in self.treasures.iter() {
+            bytes.extend_from_slice(format!("{:?}", treasure).as_bytes());
+        }
+        for deposit in self.deposits.iter() {
+            bytes.extend_from_slice(format!("{:?}", deposit).as_bytes());
+        }
+
+        xxh3_64(&bytes)
+    }
+}
+
+// What a seed produced, for bug reports and balancing (see the inspect_seed tool)
+#[derive(Debug, Clone, Serialize)]
+pub struct WorldSummary {
+    pub seed_text: String,
+    pub seed: u64,
+    pub preset: String,
+    pub width: u32,
+    pub height: u32,
+    pub fingerprint: u64,
+    pub player_tower: (u32, u32),
+    pub enemy_towers: Vec<(u32, u32)>,
+    pub tower_error: Option<String>,
+    pub quality: MapQualityReport,
+    pub treasures: Vec<TreasureSummary>,
+    pub deposits: Vec<Deposit>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TreasureSummary {
+    pub location: (u32, u32),
+    #[serde(flatten)]
+    pub treasure: Treasure,
+}
+
+impl WorldSummary {
+    pub fn new(config: &GameConfig, params: &WorldGenParams, world: &GeneratedWorld) -> Self {
+        Self {
+            seed_text: config.seed_text.clone(),
+            seed: params.seed,
+            preset: params.preset.name.clone(),
+            width: params.width,
+            height: params.height,
+            fingerprint: world.fingerprint(),
+            player_tower: world.player_tower,
+            enemy_towers: world.enemy_towers.clone(),
+            tower_error: world.tower_error.as_ref().map(|e| e.to_string()),
+            quality: world.quality.clone(),
+            treasures: world
+                .treasure_spots
+                .iter()
+                .zip(world.treasures.iter())
+                .map(|(location, treasure)| TreasureSummary {
+                    location: *location,
+                    treasure: treasure.clone(),
+                })
+                .collect(),
+            deposits: world.deposits.clone(),
+        }
+    }
+}
+
+pub struct WorldGenerator;
+
+// Everything that gets rerolled when a map fails its quality checks
+struct Terrain {
+    map: NoiseMap,
+    biomes: BiomeMap,
+    rivers: RiverNetwork,
+    towers: TowerPlacement,
+    tower_error: Option<TowerPlacementError>,
+    quality: MapQualityReport,
+}
+
+impl WorldGenerator {
+    pub fn generate(params: &WorldGenParams) -> GeneratedWorld {
+        let preset = &params.preset;
+
+        // Terrain and towers share one stream, treasure and deposits get their own
+        let mut worldgen_rng = fork_stream(params.seed, RngStream::WorldGen);
+        let mut treasure_rng = fork_stream(params.seed, RngStream::Treasures);
+        let mut deposit_rng = fork_stream(params.seed, RngStream::Deposits);
+
+        // Rerolls come from the same stream, so a seed always settles on the same map
+        let mut best: Option<Terrain> = None;
+        for attempt in 1..=preset.max_regen_attempts.max(1) {
+            let mut terrain = Self::generate_terrain(params, &mut worldgen_rng);
+            terrain.quality.attempts = attempt;
+
+            if terrain.quality.is_acceptable() {
+                best = Some(terrain);
+                break;
+            }
+
+            for issue in terrain.quality.issues.iter() {
+                log::warn!("Seed {} attempt {}: {}", params.seed, attempt, issue);
+            }
+
+            if best.as_ref().map_or(true, |best| {
+                terrain.quality.issues.len() < best.quality.issues.len()
+            }) {
+                best = Some(terrain);
+            }
+        }
+
+        let Terrain {
+            map,
+            biomes,
+            rivers,
+            towers,
+            tower_error,
+            quality,
+        } = best.expect("At least one terrain attempt");
+
+        if !quality.is_acceptable() {
+            log::warn!(
+                "Seed {} still has {} map quality issues, keeping the best attempt",
+                params.seed,
+                quality.issues.len()
+            );
+        }
+
+        let treasure_spots = create_treasure_spots(&mut treasure_rng, &biomes, &towers, preset);
+
+        // Further from home is better loot
+        let qualities: Vec<f64> = treasure_spots
+            .iter()
+            .map(|loc| treasure_quality(*loc, towers.player, params.width, params.height))
+            .collect();
+
+        let treasures = generate_treasures(&mut treasure_rng, &qualities);
+
+        let mut taken: HashSet<(u32, u32)> = treasure_spots.iter().copied().collect();
+        taken.insert(towers.player);
+        taken.extend(towers.enemies.iter().copied());
+        let deposits = create_deposits(&mut deposit_rng, &map, &biomes, &taken, preset);
+
+        GeneratedWorld {
+            map,
+            biomes,
+            rivers,
+            player_tower: towers.player,
+            enemy_towers: towers.enemies,
+            tower_error,
+            quality,
+            treasure_spots,
+            treasures,
+            deposits,
+        }
+    }
+
+    fn generate_terrain<R: Rng + ?Sized>(params: &WorldGenParams, rng: &mut R) -> Terrain {
+        let preset = &params.preset;
+
+        let map = create_map(
+            rng.gen(),
+            params.width as usize,
+            params.height as usize,
+            preset,
+        );
+        let (map, rivers) = simulate_rainfall_river_generation_erosion(
+            map,
+            preset.erosion_iterations,
+            preset.rainfall,
+        );
+
+        let mut biomes = BiomeMap::generate(rng.gen(), &map);
+        biomes.apply_rivers(&rivers);
+
+        let (towers, tower_error) = match place_towers(&map, &rivers, rng, preset) {
+            Ok(towers) => (towers, None),
+            Err(e) => {
+                log::warn!("{} (seed {}), relaxing tower spacing", e, params.seed);
+                (fallback_towers(&map, &rivers, rng, preset), Some(e))
+            }
+        };
+
+        let quality = MapQualityReport::measure(&map, &biomes, &rivers, &towers, preset);
+
+        Terrain {
+            map,
+            biomes,
+            rivers,
+            towers,
+            tower_error,
+            quality,
+        }
+    }
+}
+
+pub fn create_map(seed: u32, width: usize, height: usize, preset: &WorldGenPreset) -> NoiseMap {
+    let fbm = Fbm::<Value>::new(seed)
+        .set_octaves(preset.octaves)
+        .set_frequency(preset.frequency)
+        .set_persistence(preset.persistence);
+
+    let mut noise_map = PlaneMapBuilder::new(fbm)
+        .set_size(width, height)
+        .set_x_bounds(preset.x_bounds.0, preset.x_bounds.1)
+        .set_y_bounds(preset.y_bounds.0, preset.y_bounds.1)
+        .build();
+
+    // Get average value
+    let mut sum = 0.0;
+    for x in 0..width {
+        for y in 0..height {
+            sum += noise_map.get_value(x, y);
+        }
+    }
+    let avg = sum / (width * height) as f64;
+    log::info!("Average value: {}", avg);
+
+    // Average value should be between average_min and average_max (0.3 and 0.7 by default)
+    // Scale the map so that the average value is 0.5
+    let (avg_min, avg_max) = (preset.average_min, preset.average_max);
+    if avg < avg_min || avg > avg_max {
+        let diff = (avg / avg_min).min(avg / avg_max);
+
+        for x in 0..width {
+            for y in 0..height {
+                let val = noise_map.get_value(x, y);
+                noise_map.set_value(x, y, val * diff);
+            }
+        }
+    }
+
+    // Min and max should be between 0 and 1
+
+    // f64 so have to use fold
+    let max = noise_map.iter().fold(f64::MIN, |acc, x| acc.max(*x));
+    let min = noise_map.iter().fold(f64::MAX, |acc, x| acc.min(*x));
+    log::info!("Min: {}, Max: {}", min, max);
+
+    if min < 0.0 || max > 1.0 {
+        for x in 0..width {
+            for y in 0..height {
+                let val = noise_map.get_value(x, y);
+                if val < 0.0 || val > 1.0 {
+                    noise_map.set_value(x, y, sigmoid(val));
+                }
+            }
+        }
+    }
+
+    noise_map
+}
+
+// Hacky, from ml world, but whatev
+fn sigmoid(x: f64) -> f64 {
+    1.0 / (1.0 + (-x).exp())
+}
+
+pub fn get_index(val: f64) -> u32 {
+    match val.abs() {
+        // previously with .abs()
+        // Dark blue water
+        v if v < 0.03 => 0,
+        // Light blue water
+        v if v < 0.08 => 1,
+        v if v < 0.1 => 2,
+        v if v < 0.2 => 3,
+        v if v < 0.3 => 4,
+        v if v < 0.4 => 4,
+        v if v < 0.5 => 4,
+        v if v < 0.6 => 4,
+        // Mountains (guessing)
+        v if v < 0.7 => 5,
+        v if v < 0.8 => 5,
+        v if v < 0.9 => 5,
+        v if v <= 1.0 => 5,
+        _ => panic!("Unexpected value for color"),
+    }
+}
+
+// Flat colours for the get_index tiles, for the minimap and exported PNGs
+pub fn index_color(index: u32) -> [u8; 3] {
+    match index {
+        // Deep water
+        0 => [16, 42, 110],
+        // Shallow water
+        1 => [52, 110, 190],
+        // Sand
+        2 => [222, 206, 150],
+        // Grass
+        3 => [110, 170, 70],
+        // Forest
+        4 => [40, 105, 45],
+        // Mountain
+        5 => [130, 125, 120],
+        _ => [255, 0, 255],
+    }
+}
+
+// Ruins come in clusters, centres are picked weighted by biome and the rest of the
+// cluster is scattered around them. Never in water, on a tower, or doubled up.
+pub fn create_treasure_spots<R: Rng + ?Sized>(
+    rng: &mut R,
+    biomes: &BiomeMap,
+    towers: &TowerPlacement,
+    preset: &WorldGenPreset,
+) -> Vec<(u32, u32)> {
+    let (width, height) = biomes.size();
+    let (width, height) = (width as u32, height as u32);
+
+    let mut taken: HashSet<(u32, u32)> = HashSet::new();
+    taken.insert(towers.player);
+    taken.extend(towers.enemies.iter().copied());
+
+    let mut locs = Vec::new();
+
+    // Generate between 200 and 500 treasure spots (by default)
+    let num_treasure_spots = rng.gen_range(preset_range(preset.treasure_spots)) as usize;
+
+    // Bounded so a map that's nearly all water can't spin forever
+    let max_attempts = num_treasure_spots * TREASURE_ATTEMPTS_PER_SPOT;
+    let mut attempts = 0;
+
+    while locs.len() < num_treasure_spots && attempts < max_attempts {
+        attempts += 1;
+
+        let center = (rng.gen_range(0..width), rng.gen_range(0..height));
+        let weight = biomes.get(center.0, center.1).treasure_weight();
+        if !rng.gen_bool(weight.clamp(0.0, 1.0)) {
+            continue;
+        }
+
+        let cluster_size = rng.gen_range(preset_range(preset.ruin_cluster_size));
+        let radius = preset.ruin_cluster_radius.max(1) as i32;
+
+        let mut placed = 0;
+        for _ in 0..cluster_size * TREASURE_ATTEMPTS_PER_SPOT as u32 {
+            if placed >= cluster_size || locs.len() >= num_treasure_spots {
+                break;
+            }
+
+            let x = center.0 as i32 + rng.gen_range(-radius..=radius);
+            let y = center.1 as i32 + rng.gen_range(-radius..=radius);
+            if x < 0 || y < 0 || x >= width as i32 || y >= height as i32 {
+                continue;
+            }
+            let loc = (x as u32, y as u32);
+
+            if biomes.get(loc.0, loc.1).is_water() || taken.contains(&loc) {
+                continue;
+            }
+
+            // Less hospitable spots still get the odd ruin
+            if !rng.gen_bool(biomes.get(loc.0, loc.1).treasure_weight().clamp(0.0, 1.0)) {
+                continue;
+            }
+
+            taken.insert(loc);
+            locs.push(loc);
+            placed += 1;
+        }
+    }
+
+    if locs.len() < num_treasure_spots {
+        log::warn!(
+            "Only placed {} of {} treasure spots",
+            locs.len(),
+            num_treasure_spots
+        );
+    }
+
+    locs
+}
+
+// 0.0 next to the player tower, 1.0 at the far corner of the map
+pub fn treasure_quality(loc: (u32, u32), player_tower: (u32, u32), width: u32, height: u32) -> f64 {
+    let player = Vec2::new(player_tower.0 as f32, player_tower.1 as f32);
+    let furthest = [(0, 0), (width, 0), (0, height), (width, height)]
+        .iter()
+        .map(|(x, y)| Vec2::new(*x as f32, *y as f32).distance(player))
+        .fold(1.0, f32::max);
+
+    let distance = Vec2::new(loc.0 as f32, loc.1 as f32).distance(player);
+    (distance / furthest).clamp(0.0, 1.0) as f64
+}
+
+// Range covering the given fraction of a map dimension, never empty
+pub(crate) fn fraction_range(size: u32, low: f32, high: f32) -> std::ops::Range<u32> {
+    let start = (size as f32 * low) as u32;
+    let end = ((size as f32 * high) as u32).max(start + 1);
+    start..end
+}
+
+// Preset ranges are hand written, so don't let a min >= max blow up gen_range
+pub(crate) fn preset_range((min, max): (u32, u32)) -> std::ops::Range<u32> {
+    min..max.max(min + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Small enough to generate quickly, the golden values are for this size
+    const TEST_SIZE: u32 = 128;
+
+    const GOLDEN_SEEDS: [&str; 3] = ["442", "ldjam55", "summoning circle"];
+
+    // "<seed text>\t<fingerprint>" per line, rewrite with UPDATE_GOLDEN=1 cargo test
+    const GOLDEN_FILE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/map/world.golden");
+
+    fn generate(seed_text: &str) -> GeneratedWorld {
+        let mut config = GameConfig {
+            map_width: TEST_SIZE,
+            map_height: TEST_SIZE,
+            ..Default::default()
+        };
+        config.set_seed(seed_text);
+        WorldGenerator::generate(&WorldGenParams::from_config(&config))
+    }
+
+    #[test]
+    fn same_seed_same_world() {
+        assert_eq!(generate("442").fingerprint(), generate("442").fingerprint());
+    }
+
+    #[test]
+    fn different_seeds_differ() {
+        let fingerprints: HashSet<u64> = GOLDEN_SEEDS
+            .iter()
+            .map(|seed| generate(seed).fingerprint())
+            .collect();
+        assert_eq!(fingerprints.len(), GOLDEN_SEEDS.len());
+    }
+
+    #[test]
+    fn treasure_only_on_free_land() {
+        for seed in GOLDEN_SEEDS {
+            let world = generate(seed);
+            assert!(!world.treasure_spots.is_empty());
+            assert_eq!(world.treasures.len(), world.treasure_spots.len());
+
+            let mut seen = HashSet::new();
+            for loc in world.treasure_spots.iter() {
+                assert!(!world.biomes.get(loc.0, loc.1).is_water(), "{:?}", loc);
+                assert_ne!(*loc, world.player_tower);
+                assert!(!world.enemy_towers.contains(loc), "{:?}", loc);
+                assert!(seen.insert(*loc), "{:?} twice", loc);
+            }
+        }
+    }
+
+    #[test]
+    fn treasure_gets_better_further_out() {
+        let world = generate("442");
+        let player = world.player_tower;
+        let quality = |loc: (u32, u32)| treasure_quality(loc, player, TEST_SIZE, TEST_SIZE);
+        let distance = |loc: &(u32, u32)| {
+            Vec2::new(loc.0 as f32, loc.1 as f32)
+                .distance(Vec2::new(player.0 as f32, player.1 as f32))
+        };
+
+        assert_eq!(quality(player), 0.0);
+
+        let mut spots = world.treasure_spots.clone();
+        spots.sort_by(|a, b| distance(a).total_cmp(&distance(b)));
+        let qualities: Vec<f64> = spots.iter().map(|loc| quality(*loc)).collect();
+        assert!(qualities.iter().all(|q| (0.0..=1.0).contains(q)));
+        assert!(qualities.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert!(qualities.first() < qualities.last());
+    }
+
+    #[test]
+    fn golden_fingerprints() {
+        let actual: Vec<String> = GOLDEN_SEEDS
+            .iter()
+            .map(|seed| format!("{}\t{}", seed, generate(seed).fingerprint()))
+            .collect();
+
+        if std::env::var("UPDATE_GOLDEN").is_ok() {
+            std::fs::write(GOLDEN_FILE, actual.join("\n") + "\n").unwrap();
+            return;
+        }
+
+        let golden = std::fs::read_to_string(GOLDEN_FILE).unwrap_or_else(|_| {
+            panic!("{} is missing, record it with UPDATE_GOLDEN=1", GOLDEN_FILE)
+        });
+        let golden: Vec<&str> = golden.lines().collect();
+        assert_eq!(
+            golden, actual,
+            "World generation changed, if that's on purpose rerun with UPDATE_GOLDEN=1"
+        );
+    }
+}
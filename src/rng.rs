@@ -0,0 +1,86 @@
+use bevy::prelude::*;
+use bevy_prng::WyRand;
+use bevy_rand::prelude::*;
+use rand::SeedableRng;
+
+use crate::*;
+
+// Every subsystem draws from its own stream seeded off the root seed, so extra
+// draws in one (or a different system order) never shifts what another gets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RngStream {
+    WorldGen,
+    Treasures,
+    Combat,
+    Cosmetic,
+    Deposits,
+}
+
+impl RngStream {
+    // Fixed per stream rather than the variant order, so adding or moving one
+    // never changes what an existing seed generates
+    pub fn key(&self) -> u64 {
+        let tag = match self {
+            RngStream::WorldGen => b"worldgen",
+            RngStream::Treasures => b"treasure",
+            RngStream::Combat => b"combat\0\0",
+            RngStream::Cosmetic => b"cosmetic",
+            RngStream::Deposits => b"deposits",
+        };
+        u64::from_le_bytes(*tag)
+    }
+}
+
+pub fn fork_stream(root_seed: u64, stream: RngStream) -> EntropyComponent<WyRand> {
+    EntropyComponent::from_seed((root_seed ^ stream.key()).to_le_bytes())
+}
+
+#[derive(Resource, Deref, DerefMut)]
+pub struct CombatRng(pub EntropyComponent<WyRand>);
+
+// Only for things that don't change the game, like jitter_units
+#[derive(Resource, Deref, DerefMut)]
+pub struct CosmeticRng(pub EntropyComponent<WyRand>);
+
+// Seed is final once we get here, either typed in or from the command line
+pub fn setup_rng_streams(mut commands: Commands, config: Res<GameConfig>) {
+    let root_seed = config.rng_seed();
+    log::info!("Seed: {} ({})", config.seed_text, root_seed);
+
+    commands.insert_resource(GlobalEntropy::new(WyRand::seed_from_u64(root_seed)));
+    commands.insert_resource(CombatRng(fork_stream(root_seed, RngStream::Combat)));
+    commands.insert_resource(CosmeticRng(fork_stream(root_seed, RngStream::Cosmetic)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STREAMS: [RngStream; 5] = [
+        RngStream::WorldGen,
+        RngStream::Treasures,
+        RngStream::Combat,
+        RngStream::Cosmetic,
+        RngStream::Deposits,
+    ];
+
+    #[test]
+    fn streams_are_keyed_apart() {
+        let firsts: std::collections::HashSet<u64> = STREAMS
+            .iter()
+            .map(|stream| fork_stream(442, *stream).gen::<u64>())
+            .collect();
+        assert_eq!(firsts.len(), STREAMS.len());
+    }
+
+    #[test]
+    fn same_seed_same_stream() {
+        let mut a = fork_stream(442, RngStream::Treasures);
+        let mut b = fork_stream(442, RngStream::Treasures);
+        assert_eq!(a.gen::<u64>(), b.gen::<u64>());
+        assert_ne!(
+            fork_stream(442, RngStream::Treasures).gen::<u64>(),
+            fork_stream(443, RngStream::Treasures).gen::<u64>()
+        );
+    }
+}
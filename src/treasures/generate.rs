@@ -1,13 +1,11 @@
-use bevy::prelude::*;
-use bevy_prng::*;
-use bevy_rand::*;
 use rand_distr::{Distribution, Poisson};
+use serde::Serialize;
 use strum::IntoEnumIterator;
 use strum_macros::EnumIter;
 
 use crate::*;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct Treasure {
     pub score: u16,
     pub boons: Vec<Boon>, // 1 to 6, weighted towards 1
@@ -15,7 +13,7 @@ pub struct Treasure {
     pub slot: u8,
 }
 
-#[derive(Debug, EnumIter, Clone)]
+#[derive(Debug, EnumIter, Clone, Serialize)]
 pub enum SummonType {
     FireElemental,
     WaterElemental,
@@ -24,14 +22,14 @@ pub enum SummonType {
     GravityElemental,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct Boon {
     pub category: BoonType,
     pub operation: BoonOperation,
     pub value: u8,
 }
 
-#[derive(EnumIter, Eq, PartialEq, Debug, Clone, Copy)]
+#[derive(EnumIter, Eq, PartialEq, Debug, Clone, Copy, Serialize)]
 pub enum BoonOperation {
     Add,
     Multiply,
@@ -46,7 +44,7 @@ impl std::fmt::Display for BoonOperation {
     }
 }
 
-#[derive(EnumIter, Debug, Clone)]
+#[derive(EnumIter, Debug, Clone, Serialize)]
 pub enum BoonType {
     Health,
     Visibility,
@@ -97,24 +95,18 @@ impl std::fmt::Display for BoonType {
     }
 }
 
-pub struct TreasureGenerationPlugin;
-
-impl Plugin for TreasureGenerationPlugin {
-    fn build(&self, app: &mut App) {
-        app.add_event::<MapClick>()
-            .add_systems(OnExit(Game::MapGeneration), fill_treasures);
-    }
-}
-
-fn fill_treasures(mut treasures: ResMut<TreasureLocs>, mut rng: ResMut<GlobalEntropy<WyRand>>) {
+// One treasure per quality (0.0 to 1.0), better quality means more boons, more
+// multipliers and more summons
+pub fn generate_treasures<R: Rng + ?Sized>(rng: &mut R, qualities: &[f64]) -> Vec<Treasure> {
     let mut treasures_vec = vec![];
 
-    let dist = Poisson::new(1.4).unwrap();
+    for &quality in qualities.iter() {
+        let quality = quality.clamp(0.0, 1.0);
 
-    for _ in 0..treasures.locs.len() {
         let mut score = 100; // 100 for finding a treasure
 
-        let boons_count: f32 = dist.sample(&mut *rng);
+        let dist = Poisson::new(1.4 + quality as f32 * 1.6).unwrap();
+        let boons_count: f32 = dist.sample(rng);
         // Clamp to 1 to 6
         let boons_count = boons_count.clamp(1.0, 6.0);
         let boons_count = boons_count as u8;
@@ -122,10 +114,10 @@ fn fill_treasures(mut treasures: ResMut<TreasureLocs>, mut rng: ResMut<GlobalEnt
         // Choose a boon
         let mut boons = vec![];
         for _ in 0..boons_count {
-            let category = BoonType::iter().choose(&mut *rng).unwrap();
+            let category = BoonType::iter().choose(rng).unwrap();
 
-            // Weighted choice, 90% add, 10% multiply
-            let operation = if rng.gen_bool(0.9) {
+            // Weighted choice, 90% add, 10% multiply (up to 30% multiply far out)
+            let operation = if rng.gen_bool(0.9 - quality * 0.2) {
                 score += 20;
                 BoonOperation::Add
             } else {
@@ -139,7 +131,11 @@ fn fill_treasures(mut treasures: ResMut<TreasureLocs>, mut rng: ResMut<GlobalEnt
                 category.range_multiply()
             };
 
-            let value = rng.gen_range(min..max);
+            // Best of two rolls, sometimes, the further out the more often
+            let mut value = rng.gen_range(min..max);
+            if rng.gen_bool(quality) {
+                value = value.max(rng.gen_range(min..max));
+            }
 
             boons.push(Boon {
                 category,
@@ -148,16 +144,19 @@ fn fill_treasures(mut treasures: ResMut<TreasureLocs>, mut rng: ResMut<GlobalEnt
             });
         }
 
-        // 5% of the time, summon an elemental
-        let summon = if rng.gen_bool(0.05) {
+        // 5% of the time, summon an elemental (up to 15%)
+        let summon = if rng.gen_bool(0.05 + quality * 0.1) {
             // Remove all boons
             boons.clear();
             score += 200;
-            Some(SummonType::iter().choose(&mut *rng).unwrap())
+            Some(SummonType::iter().choose(rng).unwrap())
         } else {
             None
         };
 
+        // Bonus for pushing out
+        score += (quality * 100.0) as u16;
+
         let slot = rng.gen_range(0..8); // Slot 0 is special, it is the defense army
 
         treasures_vec.push(Treasure {
@@ -167,5 +166,5 @@ fn fill_treasures(mut treasures: ResMut<TreasureLocs>, mut rng: ResMut<GlobalEnt
             slot,
         });
     }
-    treasures.treasures = treasures_vec;
+    treasures_vec
 }
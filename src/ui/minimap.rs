@@ -0,0 +1,268 @@
+use bevy::prelude::*;
+use bevy::render::render_asset::RenderAssetUsages;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use bevy_ecs_tilemap::prelude::*;
+use bevy_mod_picking::prelude::*;
+
+use crate::*;
+
+// Longest side of the minimap in pixels, the map is downsampled to fit
+const MINIMAP_SIZE: u32 = 200;
+
+// Redrawing every frame is wasted work, fog doesn't move that fast
+const MINIMAP_REFRESH_SECONDS: f32 = 0.25;
+
+const PLAYER_TOWER_COLOR: [u8; 4] = [255, 255, 255, 255];
+const ENEMY_TOWER_COLOR: [u8; 4] = [220, 30, 30, 255];
+const UNIT_COLOR: [u8; 4] = [0, 255, 255, 255];
+const TREASURE_COLOR: [u8; 4] = [255, 210, 0, 255];
+const UNEXPLORED_COLOR: [u8; 4] = [0, 0, 0, 255];
+
+pub struct MinimapPlugin<S: States> {
+    pub state: S,
+}
+
+impl<S: States> Plugin for MinimapPlugin<S> {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(self.state.clone()), setup_minimap)
+            .add_systems(
+                Update,
+                (
+                    update_minimap_terrain.run_if(on_event::<TerrainChanged>()),
+                    draw_minimap,
+                )
+                    .chain()
+                    .run_if(resource_exists::<Minimap>)
+                    .run_if(in_state(self.state.clone())),
+            );
+    }
+}
+
+#[derive(Resource)]
+pub struct Minimap {
+    pub image: Handle<Image>,
+    // Map tiles per minimap pixel
+    pub scale: u32,
+    pub width: u32,
+    pub height: u32,
+    // Ground colours, fog and markers are drawn over a copy of this
+    terrain: Vec<[u8; 4]>,
+    timer: Timer,
+}
+
+impl Minimap {
+    // Minimap pixels go down, tiles go up
+    fn pixel_of(&self, x: u32, y: u32) -> Option<usize> {
+        let (px, py) = (x / self.scale, y / self.scale);
+        if px >= self.width || py >= self.height {
+            return None;
+        }
+        Some((px + (self.height - 1 - py) * self.width) as usize)
+    }
+
+    // Which tile a pixel on the minimap shows (the middle of its block)
+    fn tile_of(&self, px: u32, py: u32) -> (u32, u32) {
+        (
+            px * self.scale + self.scale / 2,
+            (self.height - 1 - py) * self.scale + self.scale / 2,
+        )
+    }
+
+    // Most common biome in the block of tiles behind a pixel, ties go to whichever
+    // was seen first so it doesn't flicker between redraws
+    fn block_color(&self, biomes: &BiomeMap, px: u32, py: u32) -> [u8; 4] {
+        let (map_width, map_height) = biomes.size();
+        let (x0, y0) = (px * self.scale, (self.height - 1 - py) * self.scale);
+
+        let mut counts: Vec<(Biome, u32)> = Vec::new();
+        for y in y0..(y0 + self.scale).min(map_height as u32) {
+            for x in x0..(x0 + self.scale).min(map_width as u32) {
+                let biome = biomes.get(x, y);
+                match counts.iter_mut().find(|(seen, _)| *seen == biome) {
+                    Some((_, count)) => *count += 1,
+                    None => counts.push((biome, 1)),
+                }
+            }
+        }
+
+        let mut best = (Biome::DeepWater, 0);
+        for &(biome, count) in counts.iter() {
+            if count > best.1 {
+                best = (biome, count);
+            }
+        }
+
+        let [r, g, b] = best.0.color();
+        [r, g, b, 255]
+    }
+}
+
+#[derive(Component)]
+pub struct MinimapImage;
+
+fn setup_minimap(mut commands: Commands, mut images: ResMut<Assets<Image>>, state: Res<GameState>) {
+    let (map_width, map_height) = state.map.size();
+    let (map_width, map_height) = (map_width as u32, map_height as u32);
+
+    let scale = ((map_width.max(map_height) + MINIMAP_SIZE - 1) / MINIMAP_SIZE).max(1);
+    let width = (map_width + scale - 1) / scale;
+    let height = (map_height + scale - 1) / scale;
+
+    let mut minimap = Minimap {
+        image: Handle::default(),
+        scale,
+        width,
+        height,
+        terrain: vec![],
+        timer: Timer::from_seconds(MINIMAP_REFRESH_SECONDS, TimerMode::Repeating),
+    };
+
+    let mut terrain = vec![UNEXPLORED_COLOR; (width * height) as usize];
+    for py in 0..height {
+        for px in 0..width {
+            terrain[(px + py * width) as usize] = minimap.block_color(&state.biomes, px, py);
+        }
+    }
+    minimap.terrain = terrain;
+
+    let image = Image::new_fill(
+        Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        &UNEXPLORED_COLOR,
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::default(),
+    );
+    minimap.image = images.add(image);
+
+    commands.spawn((
+        ImageBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                top: Val::Px(5.0),
+                left: Val::Px(5.0),
+                width: Val::Px(width as f32),
+                height: Val::Px(height as f32),
+                ..default()
+            },
+            image: UiImage::new(minimap.image.clone()),
+            ..default()
+        },
+        Name::from("Minimap"),
+        MinimapImage,
+        PickableBundle::default(),
+        On::<Pointer<Click>>::run(minimap_click),
+    ));
+
+    commands.insert_resource(minimap);
+}
+
+// Any edit inside a block can change which biome wins it, so redo the whole block
+fn update_minimap_terrain(
+    mut ev_changed: EventReader<TerrainChanged>,
+    mut minimap: ResMut<Minimap>,
+    state: Res<GameState>,
+) {
+    for TerrainChanged { tile } in ev_changed.read() {
+        let Some(idx) = minimap.pixel_of(tile.x, tile.y) else {
+            continue;
+        };
+        let (px, py) = (idx as u32 % minimap.width, idx as u32 / minimap.width);
+        minimap.terrain[idx] = minimap.block_color(&state.biomes, px, py);
+    }
+}
+
+fn draw_minimap(
+    time: Res<Time>,
+    mut minimap: ResMut<Minimap>,
+    mut images: ResMut<Assets<Image>>,
+    fog: Res<FogOfWar>,
+    state: Res<GameState>,
+    treasure_locs: Res<TreasureLocs>,
+    territories: Res<Territories>,
+    units: Query<&TilePos, (With<Unit>, Without<Enemy>)>,
+) {
+    if !minimap.timer.tick(time.delta()).just_finished() {
+        return;
+    }
+
+    let Some(image) = images.get_mut(&minimap.image) else {
+        return;
+    };
+
+    // Fog is sampled at the same tile the terrain colour came from
+    for py in 0..minimap.height {
+        for px in 0..minimap.width {
+            let idx = (px + py * minimap.width) as usize;
+            let (x, y) = minimap.tile_of(px, py);
+            let color = match fog.state(x, y) {
+                FogState::Unexplored => UNEXPLORED_COLOR,
+                FogState::Explored => {
+                    let [r, g, b, a] = minimap.terrain[idx];
+                    [r / 2, g / 2, b / 2, a]
+                }
+                FogState::Visible => minimap.terrain[idx],
+            };
+            image.data[idx * 4..idx * 4 + 4].copy_from_slice(&color);
+        }
+    }
+
+    let mut mark = |x: u32, y: u32, color: [u8; 4]| {
+        if let Some(idx) = minimap.pixel_of(x, y) {
+            image.data[idx * 4..idx * 4 + 4].copy_from_slice(&color);
+        }
+    };
+
+    // Only what the player has seen
+    for (x, y) in treasure_locs.locs.iter() {
+        if fog.is_explored(*x, *y) {
+            mark(*x, *y, TREASURE_COLOR);
+        }
+    }
+
+    for tile_pos in units.iter() {
+        mark(tile_pos.x, tile_pos.y, UNIT_COLOR);
+    }
+
+    // Captured towers change colour, the player's own is always known
+    for tower in territories.towers.iter() {
+        let (x, y) = tower.loc;
+        let color = match tower.owner {
+            Faction::Player => PLAYER_TOWER_COLOR,
+            Faction::Enemy => ENEMY_TOWER_COLOR,
+        };
+        if fog.is_explored(x, y) || (x, y) == state.player_tower_location {
+            mark(x, y, color);
+        }
+    }
+}
+
+fn minimap_click(
+    event: Listener<Pointer<Click>>,
+    minimap: Res<Minimap>,
+    node_q: Query<(&Node, &GlobalTransform), With<MinimapImage>>,
+    mut ev_center: EventWriter<CenterCamera>,
+) {
+    let Ok((node, transform)) = node_q.get(event.target) else {
+        return;
+    };
+
+    // UI transforms are the centre of the node, in logical pixels from the top left
+    let size = node.size();
+    let top_left = transform.translation().truncate() - size / 2.0;
+    let local = (event.pointer_location.position - top_left) / size;
+    if local.x < 0.0 || local.y < 0.0 || local.x >= 1.0 || local.y >= 1.0 {
+        return;
+    }
+
+    let px = (local.x * minimap.width as f32) as u32;
+    let py = (local.y * minimap.height as f32) as u32;
+    let (x, y) = minimap.tile_of(px, py);
+
+    ev_center.send(CenterCamera {
+        loc: TilePos { x, y },
+    });
+}
@@ -1,3 +1,13 @@
+pub mod minimap;
+pub mod notices;
+pub mod seed;
+pub mod selection;
+pub mod stockpile;
 pub mod units;
 
+pub use minimap::*;
+pub use notices::*;
+pub use seed::*;
+pub use selection::*;
+pub use stockpile::*;
 pub use units::*;
@@ -0,0 +1,92 @@
+use bevy::prelude::*;
+use bevy_mod_picking::prelude::*;
+
+use crate::*;
+
+// How long a notice stays up before it clears itself
+const NOTICE_SECONDS: f32 = 3.0;
+
+pub struct NoticesUiPlugin<S: States> {
+    pub state: S,
+}
+
+impl<S: States> Plugin for NoticesUiPlugin<S> {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(self.state.clone()), setup_notice_label)
+            .add_systems(
+                Update,
+                (show_unreachable_paths, clear_notice)
+                    .chain()
+                    .run_if(in_state(self.state.clone())),
+            );
+    }
+}
+
+#[derive(Component)]
+pub struct NoticeLabel {
+    timer: Timer,
+}
+
+// Centered just above the unit toolbar
+fn setup_notice_label(mut commands: Commands, assets: Res<GameAssets>) {
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    bottom: Val::Px(90.0),
+                    width: Val::Percent(100.0),
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                ..default()
+            },
+            Name::from("Notices"),
+            Pickable::IGNORE,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font_size: 16.0,
+                        color: Color::rgb(1.0, 0.85, 0.4),
+                        font: assets.font.clone(),
+                    },
+                ),
+                NoticeLabel {
+                    timer: Timer::from_seconds(NOTICE_SECONDS, TimerMode::Once),
+                },
+                Pickable::IGNORE,
+            ));
+        });
+}
+
+// Latest one wins, a group order that fails for everyone only says so once
+fn show_unreachable_paths(
+    mut ev_unreachable: EventReader<PathUnreachable>,
+    names: Query<&Name>,
+    mut query: Query<(&mut Text, &mut NoticeLabel)>,
+) {
+    let Some(unreachable) = ev_unreachable.read().last() else {
+        return;
+    };
+
+    let text = match names.get(unreachable.unit) {
+        Ok(name) => format!("{}: {}", name, unreachable.error),
+        Err(_) => unreachable.error.to_string(),
+    };
+
+    for (mut label, mut notice) in query.iter_mut() {
+        label.sections[0].value = text.clone();
+        notice.timer.reset();
+    }
+}
+
+fn clear_notice(time: Res<Time>, mut query: Query<(&mut Text, &mut NoticeLabel)>) {
+    for (mut label, mut notice) in query.iter_mut() {
+        if notice.timer.tick(time.delta()).just_finished() {
+            label.sections[0].value.clear();
+        }
+    }
+}
@@ -0,0 +1,165 @@
+use bevy::prelude::*;
+use bevy::window::ReceivedCharacter;
+use bevy_mod_picking::prelude::*;
+
+use crate::*;
+
+// Long enough for a phrase, short enough to fit on screen
+const MAX_SEED_LENGTH: usize = 64;
+
+pub struct SeedUiPlugin;
+
+impl Plugin for SeedUiPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SeedInput>()
+            .add_systems(OnEnter(Game::SeedEntry), setup_seed_entry)
+            .add_systems(
+                Update,
+                (seed_input, update_seed_entry)
+                    .chain()
+                    .run_if(in_state(Game::SeedEntry)),
+            )
+            .add_systems(OnExit(Game::SeedEntry), cleanup_seed_entry)
+            .add_systems(OnEnter(Game::Playing), setup_seed_label);
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct SeedInput(pub String);
+
+#[derive(Component)]
+pub struct SeedEntryScreen;
+
+#[derive(Component)]
+pub struct SeedEntryText;
+
+#[derive(Component)]
+pub struct SeedLabel;
+
+fn setup_seed_entry(mut commands: Commands, assets: Res<GameAssets>, config: Res<GameConfig>) {
+    let text_style = TextStyle {
+        font_size: 26.0,
+        color: Color::WHITE,
+        font: assets.font.clone(),
+    };
+
+    let hint_style = TextStyle {
+        font_size: 14.0,
+        color: Color::rgb(0.7, 0.7, 0.7),
+        font: assets.font.clone(),
+    };
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    flex_direction: FlexDirection::Column,
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    row_gap: Val::Px(12.0),
+                    ..default()
+                },
+                ..default()
+            },
+            Name::from("SeedEntryScreen"),
+            SeedEntryScreen,
+        ))
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section("Enter a seed", text_style.clone()));
+
+            parent
+                .spawn((
+                    NodeBundle {
+                        style: Style {
+                            min_width: Val::Px(400.0),
+                            padding: UiRect::all(Val::Px(8.0)),
+                            justify_content: JustifyContent::Center,
+                            ..default()
+                        },
+                        background_color: Color::rgb(0.15, 0.15, 0.15).into(),
+                        ..default()
+                    },
+                    Name::from("SeedEntryBox"),
+                ))
+                .with_children(|parent| {
+                    parent.spawn((TextBundle::from_section("_", text_style), SeedEntryText));
+                });
+
+            parent.spawn(TextBundle::from_section(
+                format!(
+                    "Enter to start, leave blank for {}. Also --seed <text> or {}",
+                    config.seed_text, SEED_ENV_VAR
+                ),
+                hint_style,
+            ));
+        });
+}
+
+fn seed_input(
+    mut chars: EventReader<ReceivedCharacter>,
+    keys: Res<ButtonInput<KeyCode>>,
+    mut input: ResMut<SeedInput>,
+    mut config: ResMut<GameConfig>,
+    mut game: ResMut<NextState<Game>>,
+) {
+    for event in chars.read() {
+        for c in event.char.chars() {
+            // Enter, backspace etc come through here too, they're handled below
+            if !c.is_control() && input.0.chars().count() < MAX_SEED_LENGTH {
+                input.0.push(c);
+            }
+        }
+    }
+
+    if keys.just_pressed(KeyCode::Backspace) {
+        input.0.pop();
+    }
+
+    if keys.just_pressed(KeyCode::Enter) || keys.just_pressed(KeyCode::NumpadEnter) {
+        if !input.0.trim().is_empty() {
+            config.set_seed(&input.0);
+        }
+        game.set(Game::MapGeneration);
+    }
+}
+
+fn update_seed_entry(input: Res<SeedInput>, mut query: Query<&mut Text, With<SeedEntryText>>) {
+    if !input.is_changed() {
+        return;
+    }
+
+    for mut text in query.iter_mut() {
+        text.sections[0].value = format!("{}_", input.0);
+    }
+}
+
+fn cleanup_seed_entry(mut commands: Commands, query: Query<Entity, With<SeedEntryScreen>>) {
+    for e in query.iter() {
+        commands.entity(e).despawn_recursive();
+    }
+}
+
+// Top right, so a map can be shared
+fn setup_seed_label(mut commands: Commands, assets: Res<GameAssets>, config: Res<GameConfig>) {
+    commands.spawn((
+        TextBundle::from_section(
+            format!("Seed: {}", config.seed_text),
+            TextStyle {
+                font_size: 14.0,
+                color: Color::WHITE,
+                font: assets.font.clone(),
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Px(5.0),
+            right: Val::Px(5.0),
+            ..default()
+        }),
+        Name::from("SeedLabel"),
+        SeedLabel,
+        Pickable::IGNORE,
+    ));
+}
@@ -0,0 +1,225 @@
+use bevy::core::FrameCount;
+use bevy::prelude::*;
+use bevy_mod_picking::prelude::*;
+
+use crate::*;
+
+// Screen pixels the pointer has to move before a press counts as a box instead of a click
+const BOX_SELECT_THRESHOLD: f32 = 8.0;
+
+const SELECTED_BORDER_COLOR: Color = Color::rgb(1.0, 0.85, 0.2);
+
+const CONTROL_GROUP_KEYS: [KeyCode; 10] = [
+    KeyCode::Digit0,
+    KeyCode::Digit1,
+    KeyCode::Digit2,
+    KeyCode::Digit3,
+    KeyCode::Digit4,
+    KeyCode::Digit5,
+    KeyCode::Digit6,
+    KeyCode::Digit7,
+    KeyCode::Digit8,
+    KeyCode::Digit9,
+];
+
+pub struct SelectionPlugin<S: States> {
+    pub state: S,
+}
+
+impl<S: States> Plugin for SelectionPlugin<S> {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<BoxSelect>()
+            .init_resource::<ControlGroups>()
+            .add_systems(OnEnter(self.state.clone()), setup_selection_box)
+            .add_systems(
+                Update,
+                (control_groups, highlight_selected_slots)
+                    .chain()
+                    .run_if(in_state(self.state.clone())),
+            );
+    }
+}
+
+// Dragging on the map, map_click ignores the release once it's turned into a box
+#[derive(Resource, Default)]
+pub struct BoxSelect {
+    // World position, from CursorPos
+    pub start: Vec2,
+    // Window position, for drawing the box
+    pub screen_start: Vec2,
+    pub dragged: bool,
+    // Frame the last box was let go, the click from that same release can be
+    // handled before or after DragEnd
+    pub finished_on: Option<u32>,
+}
+
+impl BoxSelect {
+    // Whether a click is really the end of a box select
+    pub fn swallows_click(&self, frame: &FrameCount) -> bool {
+        self.dragged || self.finished_on == Some(frame.0)
+    }
+}
+
+// Ctrl + number stores the selection, the number on its own brings it back
+#[derive(Resource, Default)]
+pub struct ControlGroups {
+    pub groups: [Vec<u8>; 10],
+}
+
+#[derive(Component)]
+pub struct SelectionBox;
+
+fn setup_selection_box(mut commands: Commands) {
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                border: UiRect::all(Val::Px(1.0)),
+                ..default()
+            },
+            border_color: Color::WHITE.into(),
+            background_color: Color::rgba(1.0, 1.0, 1.0, 0.1).into(),
+            visibility: Visibility::Hidden,
+            ..default()
+        },
+        Name::from("SelectionBox"),
+        SelectionBox,
+        Pickable::IGNORE,
+    ));
+}
+
+pub fn start_box_select(
+    event: Listener<Pointer<Down>>,
+    cursor: Res<CursorPos>,
+    mut box_select: ResMut<BoxSelect>,
+) {
+    if event.button != PointerButton::Primary {
+        return;
+    }
+
+    box_select.start = cursor.mouse_position;
+    box_select.screen_start = event.pointer_location.position;
+    box_select.dragged = false;
+}
+
+pub fn drag_box_select(
+    event: Listener<Pointer<Drag>>,
+    mut box_select: ResMut<BoxSelect>,
+    mut box_q: Query<(&mut Style, &mut Visibility), With<SelectionBox>>,
+) {
+    if event.button != PointerButton::Primary {
+        return;
+    }
+
+    let position = event.pointer_location.position;
+    if position.distance(box_select.screen_start) > BOX_SELECT_THRESHOLD {
+        box_select.dragged = true;
+    }
+
+    if !box_select.dragged {
+        return;
+    }
+
+    let min = position.min(box_select.screen_start);
+    let size = (position - box_select.screen_start).abs();
+    for (mut style, mut visibility) in box_q.iter_mut() {
+        style.left = Val::Px(min.x);
+        style.top = Val::Px(min.y);
+        style.width = Val::Px(size.x);
+        style.height = Val::Px(size.y);
+        *visibility = Visibility::Visible;
+    }
+}
+
+// Shift or ctrl adds to what's already selected
+pub fn end_box_select(
+    event: Listener<Pointer<DragEnd>>,
+    cursor: Res<CursorPos>,
+    frame: Res<FrameCount>,
+    mut box_select: ResMut<BoxSelect>,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut selected_unit: ResMut<SelectedUnit>,
+    mut box_q: Query<&mut Visibility, With<SelectionBox>>,
+    units: Query<(Entity, &Transform, &Slot), (With<Unit>, Without<Enemy>)>,
+) {
+    if event.button != PointerButton::Primary || !box_select.dragged {
+        return;
+    }
+    box_select.dragged = false;
+    box_select.finished_on = Some(frame.0);
+
+    for mut visibility in box_q.iter_mut() {
+        *visibility = Visibility::Hidden;
+    }
+
+    let area = Rect::from_corners(box_select.start, cursor.mouse_position);
+
+    let mut selection = if keyboard_input.any_pressed([
+        KeyCode::ShiftLeft,
+        KeyCode::ShiftRight,
+        KeyCode::ControlLeft,
+        KeyCode::ControlRight,
+    ]) {
+        selected_unit.units.clone()
+    } else {
+        vec![]
+    };
+
+    for (e, transform, slot) in units.iter() {
+        if area.contains(transform.translation.xy()) && !selection.contains(&(slot.slot, e)) {
+            selection.push((slot.slot, e));
+        }
+    }
+
+    log::info!("Box selected {} units", selection.len());
+    selected_unit.set(selection);
+}
+
+fn control_groups(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut groups: ResMut<ControlGroups>,
+    mut selected_unit: ResMut<SelectedUnit>,
+    game_state: Res<GameState>,
+) {
+    let ctrl = keyboard_input.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]);
+
+    for (n, key) in CONTROL_GROUP_KEYS.iter().enumerate() {
+        if !keyboard_input.just_pressed(*key) {
+            continue;
+        }
+
+        if ctrl {
+            groups.groups[n] = selected_unit.units.iter().map(|(slot, _)| *slot).collect();
+            log::info!("Control group {}: {:?}", n, groups.groups[n]);
+            continue;
+        }
+
+        // Units that have died since the group was made are skipped
+        let units = groups.groups[n]
+            .iter()
+            .filter_map(|slot| match game_state.units[*slot as usize] {
+                UnitEntry::Summoned(e) => Some((*slot, e)),
+                _ => None,
+            })
+            .collect();
+        selected_unit.set(units);
+    }
+}
+
+fn highlight_selected_slots(
+    selected_unit: Res<SelectedUnit>,
+    mut slots: Query<(&Slot, &mut Style, &mut BorderColor), With<Button>>,
+) {
+    for (slot, mut style, mut border_color) in slots.iter_mut() {
+        let border = if selected_unit.is_selected(slot.slot) {
+            UiRect::all(Val::Px(4.0))
+        } else {
+            UiRect::all(Val::ZERO)
+        };
+
+        if style.border != border {
+            style.border = border;
+            border_color.0 = SELECTED_BORDER_COLOR;
+        }
+    }
+}
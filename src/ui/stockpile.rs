@@ -0,0 +1,67 @@
+use bevy::prelude::*;
+use bevy_mod_picking::prelude::*;
+
+use crate::*;
+
+pub struct StockpileUiPlugin<S: States> {
+    pub state: S,
+}
+
+impl<S: States> Plugin for StockpileUiPlugin<S> {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(self.state.clone()), setup_stockpile_label)
+            .add_systems(
+                Update,
+                update_stockpile_label.run_if(in_state(self.state.clone())),
+            );
+    }
+}
+
+#[derive(Component)]
+pub struct StockpileLabel;
+
+fn stockpile_text(stockpile: &Stockpile) -> String {
+    format!(
+        "Ore: {}  Crystal: {}",
+        stockpile.ore as u32, stockpile.crystal as u32
+    )
+}
+
+// Under the seed label
+fn setup_stockpile_label(
+    mut commands: Commands,
+    assets: Res<GameAssets>,
+    game_state: Res<GameState>,
+) {
+    commands.spawn((
+        TextBundle::from_section(
+            stockpile_text(&game_state.stockpile),
+            TextStyle {
+                font_size: 14.0,
+                color: Color::WHITE,
+                font: assets.font.clone(),
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Px(24.0),
+            right: Val::Px(5.0),
+            ..default()
+        }),
+        Name::from("StockpileLabel"),
+        StockpileLabel,
+        Pickable::IGNORE,
+    ));
+}
+
+fn update_stockpile_label(
+    game_state: Res<GameState>,
+    mut query: Query<&mut Text, With<StockpileLabel>>,
+) {
+    let text = stockpile_text(&game_state.stockpile);
+    for mut label in query.iter_mut() {
+        if label.sections[0].value != text {
+            label.sections[0].value = text.clone();
+        }
+    }
+}
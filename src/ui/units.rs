@@ -26,7 +26,14 @@ impl<S: States> Plugin for UnitsUiPlugin<S> {
                 add_unit_confirm.run_if(on_event::<AddUnitConfirm>()),
             )
             .add_systems(Update, go_to_unit.run_if(on_event::<GoToUnit>()))
-            .add_systems(Update, unit_panel.run_if(in_state(self.state.clone())));
+            .add_systems(Update, free_unit_slots.run_if(on_event::<UnitDied>()))
+            .add_systems(Update, unit_panel.run_if(in_state(self.state.clone())))
+            .add_systems(
+                Update,
+                update_unit_panel_orders
+                    .after(unit_panel)
+                    .run_if(in_state(self.state.clone())),
+            );
     }
 }
 
@@ -39,6 +46,37 @@ pub struct DigButton;
 #[derive(Component)]
 pub struct UnitPanelUnitStats;
 
+#[derive(Component)]
+pub struct UnitPanelOrders;
+
+fn orders_text(orders: Option<&UnitOrders>) -> String {
+    let mut text = String::from("Orders:");
+    match orders {
+        Some(orders) if !orders.is_idle() => {
+            for (n, order) in orders.iter().enumerate() {
+                text.push_str(&format!("\n{}. {}", n + 1, order));
+            }
+        }
+        _ => text.push_str(" none"),
+    }
+    text
+}
+
+fn update_unit_panel_orders(
+    selected_unit: Res<SelectedUnit>,
+    unit_query: Query<&UnitOrders>,
+    mut text_query: Query<&mut Text, With<UnitPanelOrders>>,
+) {
+    let orders = selected_unit.e.and_then(|e| unit_query.get(e).ok());
+    let text = orders_text(orders);
+
+    for mut label in text_query.iter_mut() {
+        if label.sections[0].value != text {
+            label.sections[0].value = text.clone();
+        }
+    }
+}
+
 fn unit_panel(
     mut commands: Commands,
     selected_unit: Res<SelectedUnit>,
@@ -145,6 +183,14 @@ fn unit_panel(
                         UnitPanelUnitStats
                         ));
 
+                        parent.spawn((
+                            TextBundle {
+                                text: Text::from_section(orders_text(None), text_style.clone()),
+                                ..default()
+                            },
+                            UnitPanelOrders,
+                        ));
+
                         parent
                             .spawn((
                                 ButtonBundle {
@@ -164,6 +210,29 @@ fn unit_panel(
     }
 }
 
+// The slot can be summoned into again
+fn free_unit_slots(
+    mut commands: Commands,
+    mut ev_died: EventReader<UnitDied>,
+    button_query: Query<(Entity, &Slot), With<Button>>,
+    assets: Res<GameAssets>,
+) {
+    for UnitDied { slot, .. } in ev_died.read() {
+        let Some(slot) = slot else {
+            continue;
+        };
+
+        for (e, button_slot) in button_query.iter() {
+            if button_slot.slot == *slot {
+                commands.entity(e).insert((
+                    UiImage::new(assets.icons.plus.clone()),
+                    AddUnitButton::new(*slot),
+                ));
+            }
+        }
+    }
+}
+
 fn go_to_unit(
     mut selected_unit: ResMut<SelectedUnit>,
     mut ev_gotounit: EventReader<GoToUnit>,
@@ -173,8 +242,7 @@ fn go_to_unit(
     for GoToUnit { slot } in ev_gotounit.read() {
         for (tile_pos, unit_slot, e) in q.iter() {
             if *slot == unit_slot.slot {
-                selected_unit.unit = Some(*slot);
-                selected_unit.e = Some(e);
+                selected_unit.select(*slot, e);
                 ev_centercamera.send(CenterCamera { loc: *tile_pos });
             }
         }
@@ -272,6 +340,7 @@ fn add_unit_confirm(
                     ..default()
                 },
                 UnitUninitialized,
+                UnitOrders::default(),
                 Slot { slot: *slot },
                 TilePos {
                     x: spawn_pos.x as u32,
@@ -283,8 +352,7 @@ fn add_unit_confirm(
         // Update the game state
         game_state.units[*slot as usize] = UnitEntry::Summoned(id);
 
-        selected_unit.unit = Some(*slot);
-        selected_unit.e = Some(id);
+        selected_unit.select(*slot, id);
 
         // Update the button
         for (e, button_slot, _button, _image) in button_query.iter_mut() {
@@ -456,6 +524,9 @@ fn interaction(
     adding_unit: Option<Res<AddingUnit>>,
     mut selected_unit: ResMut<SelectedUnit>,
     mut ev_gotounit: EventWriter<GoToUnit>,
+    mut orders_q: Query<(&TilePos, &mut UnitOrders)>,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    game_state: Res<GameState>,
 ) {
     for (
         e,
@@ -487,15 +558,13 @@ fn interaction(
 
         if go_to_tower.is_some() && *interaction == Interaction::Pressed {
             ev_gototower.send(GoToTowerEvent);
-            selected_unit.unit = None;
-            selected_unit.e = None;
+            selected_unit.clear();
             style.border = UiRect::all(Val::ZERO);
         }
 
         if add_unit.is_some() && *interaction == Interaction::Pressed {
             ev_addunit.send(AddUnitEvent);
-            selected_unit.unit = None;
-            selected_unit.e = None;
+            selected_unit.clear();
             style.border = UiRect::all(Val::ZERO);
         }
 
@@ -506,8 +575,7 @@ fn interaction(
                     slot: slot,
                     unit: UnitType::Scout,
                 });
-                selected_unit.unit = None;
-                selected_unit.e = None;
+                selected_unit.clear();
             }
             style.border = UiRect::all(Val::ZERO);
         }
@@ -519,8 +587,7 @@ fn interaction(
                     slot: slot,
                     unit: UnitType::Excavation,
                 });
-                selected_unit.unit = None;
-                selected_unit.e = None;
+                selected_unit.clear();
             }
             style.border = UiRect::all(Val::ZERO);
         }
@@ -532,23 +599,32 @@ fn interaction(
                     slot: slot,
                     unit: UnitType::Attack,
                 });
-                selected_unit.unit = None;
-                selected_unit.e = None;
+                selected_unit.clear();
             }
             style.border = UiRect::all(Val::ZERO);
         }
 
         if slot.as_ref().is_some() && *interaction == Interaction::Pressed {
             let slot = slot.unwrap().slot;
-            selected_unit.unit = Some(slot);
-            style.border = UiRect::all(Val::Px(4.0));
-            ev_gotounit.send(GoToUnit { slot });
+            if keyboard_input.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]) {
+                // Add to (or take out of) the group, without moving the camera
+                if let UnitEntry::Summoned(e) = game_state.units[slot as usize] {
+                    selected_unit.toggle(slot, e);
+                }
+            } else {
+                selected_unit.clear();
+                selected_unit.unit = Some(slot);
+                style.border = UiRect::all(Val::Px(4.0));
+                ev_gotounit.send(GoToUnit { slot });
+            }
         }
 
         if dig_button.as_ref().is_some() && *interaction == Interaction::Pressed {
-            commands
-                .entity(selected_unit.e.unwrap())
-                .insert(Digging::new());
+            let unit = selected_unit.e.unwrap();
+            if let Ok((tile_pos, mut orders)) = orders_q.get_mut(unit) {
+                let append = is_appending(&keyboard_input);
+                give_order(&mut commands, unit, &mut orders, Order::Dig(*tile_pos), append);
+            }
         }
     }
 }
@@ -0,0 +1,344 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use bevy_ecs_tilemap::prelude::*;
+
+use crate::*;
+
+// Tiles (in any direction, diagonals count as one) a unit will fight across
+pub const ENGAGE_RANGE: u32 = 2;
+
+// Attacks per second for each point of battle_speed, so 10 is one a second
+pub const BATTLE_SPEED_SCALE: f32 = 0.1;
+
+// Each attack hits for damage * members, give or take this much
+const DAMAGE_SPREAD: f32 = 0.25;
+
+pub struct CombatPlugin<S: States> {
+    pub state: S,
+}
+
+impl<S: States> Plugin for CombatPlugin<S> {
+    fn build(&self, app: &mut App) {
+        app.add_event::<DealDamage>()
+            .add_event::<UnitDied>()
+            .add_systems(
+                Update,
+                (engage_hostiles, resolve_attacks, apply_damage)
+                    .chain()
+                    .run_if(in_state(self.state.clone())),
+            );
+    }
+}
+
+// Who a unit is fighting, and how long until it swings again
+#[derive(Component)]
+pub struct Engaged {
+    pub target: Entity,
+    pub cooldown: f32,
+}
+
+fn attack_interval(unit: &Unit) -> Option<f32> {
+    if unit.battle_speed == 0 {
+        return None;
+    }
+    Some(1.0 / (unit.battle_speed as f32 * BATTLE_SPEED_SCALE))
+}
+
+pub(crate) fn in_range(a: &TilePos, b: &TilePos) -> bool {
+    a.x.abs_diff(b.x).max(a.y.abs_diff(b.y)) <= ENGAGE_RANGE
+}
+
+// Ours fight theirs. An Attack order's target comes first, otherwise the closest.
+fn engage_hostiles(
+    mut commands: Commands,
+    units: Query<(
+        Entity,
+        &TilePos,
+        &Unit,
+        Has<Enemy>,
+        Has<Engaged>,
+        Option<&UnitOrders>,
+    )>,
+) {
+    for (e, tile_pos, unit, enemy, engaged, orders) in units.iter() {
+        if engaged {
+            continue;
+        }
+        let Some(interval) = attack_interval(unit) else {
+            continue;
+        };
+
+        let hostiles = units
+            .iter()
+            .filter(|(_, other_pos, _, other_enemy, _, _)| {
+                *other_enemy != enemy && in_range(tile_pos, other_pos)
+            })
+            .map(|(other, other_pos, ..)| (other, other_pos));
+
+        let ordered = orders.and_then(|orders| match orders.current {
+            Some(Order::Attack(target)) => Some(target),
+            _ => None,
+        });
+
+        let mut closest: Option<(Entity, u32)> = None;
+        for (other, other_pos) in hostiles {
+            if Some(other) == ordered {
+                closest = Some((other, 0));
+                break;
+            }
+            let distance = tile_pos.x.abs_diff(other_pos.x) + tile_pos.y.abs_diff(other_pos.y);
+            if closest.map_or(true, |(_, best)| distance < best) {
+                closest = Some((other, distance));
+            }
+        }
+
+        if let Some((target, _)) = closest {
+            log::info!("{} unit engaging", unit.unit_type);
+            commands.entity(e).insert(Engaged {
+                target,
+                cooldown: interval,
+            });
+        }
+    }
+}
+
+fn resolve_attacks(
+    mut commands: Commands,
+    mut attackers: Query<(Entity, &TilePos, &Unit, &mut Engaged)>,
+    targets: Query<&TilePos, With<Unit>>,
+    mut rng: ResMut<CombatRng>,
+    mut ev_damage: EventWriter<DealDamage>,
+    time: Res<Time>,
+) {
+    for (e, tile_pos, unit, mut engaged) in attackers.iter_mut() {
+        // Gone or got away, engage_hostiles picks a new one next frame
+        let still_there = targets
+            .get(engaged.target)
+            .is_ok_and(|target_pos| in_range(tile_pos, target_pos));
+        let interval = attack_interval(unit);
+        if !still_there || interval.is_none() {
+            commands.entity(e).remove::<Engaged>();
+            continue;
+        }
+
+        engaged.cooldown -= time.delta_seconds();
+        if engaged.cooldown > 0.0 {
+            continue;
+        }
+        engaged.cooldown += interval.unwrap();
+
+        let roll = rng.gen_range(1.0 - DAMAGE_SPREAD..=1.0 + DAMAGE_SPREAD);
+        ev_damage.send(DealDamage {
+            target: engaged.target,
+            amount: unit.damage as f32 * unit.members as f32 * roll,
+        });
+    }
+}
+
+// Every health_per_member of damage taken takes a member (and their sprite) with
+// it, the last one stays until the unit is dead. At zero the whole unit is gone and
+// its slot opens up again.
+fn apply_damage(
+    mut commands: Commands,
+    mut ev_damage: EventReader<DealDamage>,
+    mut ev_died: EventWriter<UnitDied>,
+    mut units: Query<(Entity, &mut Unit, Option<&Slot>, Option<&Children>)>,
+    visuals: Query<(), With<UnitVisual>>,
+    mut game_state: ResMut<GameState>,
+    mut selected_unit: ResMut<SelectedUnit>,
+) {
+    // Several hits in a frame land together, so nothing dies twice
+    let mut totals: HashMap<Entity, f32> = HashMap::new();
+    for DealDamage { target, amount } in ev_damage.read() {
+        *totals.entry(*target).or_default() += amount;
+    }
+
+    for (target, amount) in totals {
+        let Ok((e, mut unit, slot, children)) = units.get_mut(target) else {
+            continue;
+        };
+
+        let before = unit.current_health;
+        unit.current_health -= amount;
+
+        if unit.current_health <= 0.0 {
+            log::info!("{} unit destroyed", unit.unit_type);
+            commands.entity(e).despawn_recursive();
+
+            let slot = slot.map(|slot| slot.slot);
+            if let Some(slot) = slot {
+                game_state.units[slot as usize] = UnitEntry::Available;
+                selected_unit.remove(slot);
+            }
+
+            ev_died.send(UnitDied { unit: e, slot });
+            continue;
+        }
+
+        let health_per_member = unit.health_per_member.max(1) as f32;
+        let members_lost = |health: f32| ((unit.total_health - health) / health_per_member) as u8;
+        let lost = members_lost(unit.current_health)
+            .saturating_sub(members_lost(before))
+            .min(unit.members.saturating_sub(1));
+        if lost == 0 {
+            continue;
+        }
+
+        unit.members -= lost;
+        log::info!("{} unit lost {} members", unit.unit_type, lost);
+
+        for child in children
+            .into_iter()
+            .flat_map(|children| children.iter())
+            .filter(|child| visuals.contains(**child))
+            .take(lost as usize)
+        {
+            commands.entity(*child).despawn_recursive();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+
+    fn spawn_unit(world: &mut World, unit: Unit, x: u32, y: u32, enemy: bool) -> Entity {
+        let members = unit.members;
+        let mut entity = world.spawn((unit, TilePos { x, y }, UnitOrders::default()));
+        if enemy {
+            entity.insert(Enemy);
+        }
+        entity.with_children(|p| {
+            for _ in 0..members {
+                p.spawn(UnitVisual);
+            }
+        });
+        entity.id()
+    }
+
+    fn target_of(world: &World, e: Entity) -> Option<Entity> {
+        world.get::<Engaged>(e).map(|engaged| engaged.target)
+    }
+
+    fn damage_world() -> World {
+        let mut world = World::new();
+        world.insert_resource(GameState::default());
+        world.init_resource::<SelectedUnit>();
+        world.init_resource::<Events<DealDamage>>();
+        world.init_resource::<Events<UnitDied>>();
+        world
+    }
+
+    fn hit(world: &mut World, target: Entity, amount: f32) {
+        world.send_event(DealDamage { target, amount });
+        world.run_system_once(apply_damage);
+        // A fresh reader every run, so don't let it see this hit again
+        world.resource_mut::<Events<DealDamage>>().clear();
+    }
+
+    fn visuals_left(world: &World, e: Entity) -> usize {
+        world.get::<Children>(e).map_or(0, |children| {
+            children
+                .iter()
+                .filter(|child| world.get::<UnitVisual>(**child).is_some())
+                .count()
+        })
+    }
+
+    #[test]
+    fn hostiles_in_range_engage() {
+        let mut world = World::new();
+        let ours = spawn_unit(&mut world, Unit::attack(), 5, 5, false);
+        let friend = spawn_unit(&mut world, Unit::scout(), 4, 5, false);
+        let near = spawn_unit(&mut world, Unit::attack(), 6, 5, true);
+        let far = spawn_unit(&mut world, Unit::attack(), 7, 7, true);
+        let away = spawn_unit(&mut world, Unit::attack(), 20, 20, true);
+        world.run_system_once(engage_hostiles);
+
+        // Closest hostile, never a friend
+        assert_eq!(target_of(&world, ours), Some(near));
+        assert_eq!(target_of(&world, friend), Some(near));
+        assert_eq!(target_of(&world, near), Some(ours));
+        assert_eq!(target_of(&world, far), Some(ours));
+        assert_eq!(target_of(&world, away), None);
+    }
+
+    #[test]
+    fn attack_orders_pick_the_target() {
+        let mut world = World::new();
+        let ours = spawn_unit(&mut world, Unit::attack(), 5, 5, false);
+        spawn_unit(&mut world, Unit::attack(), 6, 5, true);
+        let far = spawn_unit(&mut world, Unit::attack(), 7, 7, true);
+        world.get_mut::<UnitOrders>(ours).unwrap().current = Some(Order::Attack(far));
+        world.run_system_once(engage_hostiles);
+
+        assert_eq!(target_of(&world, ours), Some(far));
+    }
+
+    #[test]
+    fn damage_takes_members() {
+        let mut world = damage_world();
+        let e = spawn_unit(&mut world, Unit::attack(), 5, 5, true);
+
+        // 60 health a member, so 130 is two of them
+        hit(&mut world, e, 130.0);
+        assert_eq!(world.get::<Unit>(e).unwrap().members, 18);
+        assert_eq!(visuals_left(&world, e), 18);
+
+        // Carries over, 180 taken is three
+        hit(&mut world, e, 50.0);
+        assert_eq!(world.get::<Unit>(e).unwrap().members, 17);
+        assert_eq!(visuals_left(&world, e), 17);
+
+        // The last one stands until the unit is dead
+        hit(&mut world, e, 1019.0);
+        assert_eq!(world.get::<Unit>(e).unwrap().members, 1);
+        assert_eq!(visuals_left(&world, e), 1);
+    }
+
+    #[test]
+    fn scouts_keep_their_members() {
+        let mut world = damage_world();
+        let e = spawn_unit(&mut world, Unit::scout(), 5, 5, false);
+
+        hit(&mut world, e, 45.0);
+        assert_eq!(world.get::<Unit>(e).unwrap().members, 5);
+        assert_eq!(visuals_left(&world, e), 5);
+    }
+
+    #[test]
+    fn death_frees_the_slot() {
+        let mut world = damage_world();
+        let e = spawn_unit(&mut world, Unit::scout(), 5, 5, false);
+        world.entity_mut(e).insert(Slot { slot: 0 });
+        world.resource_mut::<GameState>().units[0] = UnitEntry::Summoned(e);
+        world.resource_mut::<SelectedUnit>().select(0, e);
+
+        // Two hits in one frame that only kill together
+        world.send_event(DealDamage {
+            target: e,
+            amount: 30.0,
+        });
+        hit(&mut world, e, 30.0);
+
+        assert!(world.get_entity(e).is_none());
+        assert_eq!(world.entities().len(), 0);
+        assert!(matches!(
+            world.resource::<GameState>().units[0],
+            UnitEntry::Available
+        ));
+        assert!(!world.resource::<SelectedUnit>().is_selected(0));
+        assert_eq!(world.resource::<SelectedUnit>().unit, None);
+
+        let events = world.resource::<Events<UnitDied>>();
+        let died: Vec<(Entity, Option<u8>)> = events
+            .get_reader()
+            .read(events)
+            .map(|died| (died.unit, died.slot))
+            .collect();
+        assert_eq!(died, vec![(e, Some(0))]);
+    }
+}
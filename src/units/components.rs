@@ -1,3 +1,5 @@
+use std::collections::VecDeque;
+
 use bevy::prelude::{Component, Deref, DerefMut, Entity, Handle, Image, Vec2};
 use bevy_ecs_tilemap::prelude::TilePos;
 
@@ -127,9 +129,26 @@ impl UnitType {
 #[derive(Component)]
 pub struct UnitVisual;
 
+// Not ours, only shown while in a visible tile
+#[derive(Component)]
+pub struct Enemy;
+
 #[derive(Component)]
 pub struct UnitDirection {
     pub direction: Vec2,
     pub destination: Vec2,
     pub destination_in_tile_pos: TilePos,
 }
+
+// Where a unit has been told to go, plan_paths turns this into a UnitPath
+#[derive(Component)]
+pub struct MoveTo {
+    pub destination: TilePos,
+}
+
+// What's left of the route, UnitDirection always points at the front waypoint
+#[derive(Component)]
+pub struct UnitPath {
+    pub destination: TilePos,
+    pub waypoints: VecDeque<TilePos>,
+}
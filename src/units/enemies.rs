@@ -0,0 +1,145 @@
+use bevy::prelude::*;
+use bevy_ecs_tilemap::prelude::*;
+
+use crate::*;
+
+// What each enemy tower keeps stood around it
+const GARRISON: [UnitType; 2] = [UnitType::Attack, UnitType::Scout];
+
+// How often an enemy tower replaces garrison units it has lost
+const REINFORCE_SECONDS: f32 = 60.0;
+
+// So they can be told apart from ours once they're in sight
+pub const ENEMY_UNIT_TINT: Color = Color::rgb(1.0, 0.45, 0.45);
+
+pub struct EnemiesPlugin<S: States> {
+    pub state: S,
+}
+
+impl<S: States> Plugin for EnemiesPlugin<S> {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(ReinforceTimer(Timer::from_seconds(
+            REINFORCE_SECONDS,
+            TimerMode::Repeating,
+        )))
+        .add_systems(OnEnter(self.state.clone()), spawn_garrisons)
+        .add_systems(
+            Update,
+            reinforce_garrisons.run_if(in_state(self.state.clone())),
+        );
+    }
+}
+
+// Which tower an enemy unit was spawned to guard
+#[derive(Component)]
+pub struct Garrison {
+    pub tower: (u32, u32),
+}
+
+#[derive(Resource)]
+pub struct ReinforceTimer(pub Timer);
+
+fn spawn_garrisons(
+    mut commands: Commands,
+    state: Res<GameState>,
+    chunks: Res<MapChunks>,
+    territories: Res<Territories>,
+    garrisons: Query<&Garrison>,
+    mut ev_addunitcomplete: EventWriter<AddUnitComplete>,
+) {
+    if fill_garrisons(
+        &mut commands,
+        &territories.towers,
+        &garrisons,
+        &state,
+        &chunks,
+    ) {
+        ev_addunitcomplete.send(AddUnitComplete);
+    }
+}
+
+fn reinforce_garrisons(
+    mut commands: Commands,
+    mut timer: ResMut<ReinforceTimer>,
+    time: Res<Time>,
+    state: Res<GameState>,
+    chunks: Res<MapChunks>,
+    territories: Res<Territories>,
+    garrisons: Query<&Garrison>,
+    mut ev_addunitcomplete: EventWriter<AddUnitComplete>,
+) {
+    if !timer.0.tick(time.delta()).just_finished() {
+        return;
+    }
+    if fill_garrisons(
+        &mut commands,
+        &territories.towers,
+        &garrisons,
+        &state,
+        &chunks,
+    ) {
+        ev_addunitcomplete.send(AddUnitComplete);
+    }
+}
+
+// Tops every tower the enemy still owns back up to a full GARRISON, captured
+// ones stop getting reinforcements. True if anything was spawned.
+fn fill_garrisons(
+    commands: &mut Commands,
+    towers: &[TerritoryTower],
+    garrisons: &Query<&Garrison>,
+    state: &GameState,
+    chunks: &MapChunks,
+) -> bool {
+    let mut spawned = false;
+
+    for tower in towers.iter() {
+        if tower.owner != Faction::Enemy {
+            continue;
+        }
+
+        let present = garrisons
+            .iter()
+            .filter(|garrison| garrison.tower == tower.loc)
+            .count();
+        let missing = &GARRISON[present.min(GARRISON.len())..];
+        if missing.is_empty() {
+            continue;
+        }
+
+        let center = TilePos {
+            x: tower.loc.0,
+            y: tower.loc.1,
+        };
+        let spots = formation(state, center, missing);
+
+        for (unit_type, tile_pos) in missing.iter().zip(spots) {
+            let unit = match unit_type {
+                UnitType::Scout => Unit::scout(),
+                UnitType::Excavation => Unit::excavation(),
+                UnitType::Attack => Unit::attack(),
+            };
+            let transform =
+                Transform::from_translation(chunks.tile_to_world(&tile_pos).extend(3.5));
+
+            commands.spawn((
+                Name::from("Enemy unit"),
+                unit,
+                SpatialBundle {
+                    transform,
+                    // Fog of war shows them once they're in sight
+                    visibility: Visibility::Hidden,
+                    ..default()
+                },
+                UnitUninitialized,
+                UnitOrders::default(),
+                Enemy,
+                Garrison { tower: tower.loc },
+                tile_pos,
+            ));
+            spawned = true;
+        }
+    }
+
+    spawned
+}
@@ -1,4 +1,29 @@
 use bevy::prelude::{Entity, Event};
+use bevy_ecs_tilemap::prelude::TilePos;
+
+use crate::PathError;
 
 #[derive(Event)]
 pub struct AddUnitComplete;
+
+// A move order that couldn't be planned, the unit stays where it is
+#[derive(Event, Debug)]
+pub struct PathUnreachable {
+    pub unit: Entity,
+    pub destination: TilePos,
+    pub error: PathError,
+}
+
+// Combat sends these, anything else that hurts units can too
+#[derive(Event, Debug)]
+pub struct DealDamage {
+    pub target: Entity,
+    pub amount: f32,
+}
+
+// Already despawned by the time this is read, slot is None for enemy units
+#[derive(Event, Debug)]
+pub struct UnitDied {
+    pub unit: Entity,
+    pub slot: Option<u8>,
+}
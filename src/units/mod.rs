@@ -1,9 +1,15 @@
+pub mod combat;
 pub mod components;
+pub mod enemies;
 pub mod events;
+pub mod orders;
 pub mod plugin;
 pub mod resources;
 
+pub use combat::*;
 pub use components::*;
+pub use enemies::*;
 pub use events::*;
+pub use orders::*;
 pub use plugin::*;
 pub use resources::*;
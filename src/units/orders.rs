@@ -0,0 +1,495 @@
+use std::collections::{HashSet, VecDeque};
+
+use bevy::prelude::*;
+use bevy_ecs_tilemap::prelude::*;
+
+use crate::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Order {
+    Move(TilePos),
+    // Walks there first if it isn't already standing on it
+    Dig(TilePos),
+    Attack(Entity),
+    // Back and forth until something else gets queued
+    Patrol { from: TilePos, to: TilePos },
+    Follow(Entity),
+    ReturnToTower,
+}
+
+impl std::fmt::Display for Order {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Order::Move(tile) => write!(f, "Move to {}, {}", tile.x, tile.y),
+            Order::Dig(tile) => write!(f, "Dig at {}, {}", tile.x, tile.y),
+            Order::Attack(_) => write!(f, "Attack"),
+            Order::Patrol { from, to } => {
+                write!(f, "Patrol {}, {} - {}, {}", from.x, from.y, to.x, to.y)
+            }
+            Order::Follow(_) => write!(f, "Follow"),
+            Order::ReturnToTower => write!(f, "Return to tower"),
+        }
+    }
+}
+
+#[derive(Component, Default)]
+pub struct UnitOrders {
+    pub current: Option<Order>,
+    pub queue: VecDeque<Order>,
+    // Where a chased unit was when we last planned a path to it
+    chasing: Option<TilePos>,
+    last: Option<Order>,
+}
+
+impl UnitOrders {
+    pub fn iter(&self) -> impl Iterator<Item = &Order> {
+        self.current.iter().chain(self.queue.iter())
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.current.is_none() && self.queue.is_empty()
+    }
+
+    // Where the unit should be once everything queued is done, for chaining patrols
+    pub fn end_tile(&self, from: TilePos) -> TilePos {
+        self.iter().fold(from, |end, order| match order {
+            Order::Move(tile) | Order::Dig(tile) | Order::Patrol { to: tile, .. } => *tile,
+            _ => end,
+        })
+    }
+
+    fn finish(&mut self) {
+        self.last = self.current.take();
+        self.chasing = None;
+    }
+}
+
+// Shift appends to the queue, otherwise whatever the unit was doing is dropped
+pub fn give_order(
+    commands: &mut Commands,
+    unit: Entity,
+    orders: &mut UnitOrders,
+    order: Order,
+    append: bool,
+) {
+    if !append {
+        orders.queue.clear();
+        orders.finish();
+        commands
+            .entity(unit)
+            .remove::<(MoveTo, UnitPath, UnitDirection, Digging)>();
+    }
+    orders.queue.push_back(order);
+}
+
+pub fn is_appending(keyboard_input: &ButtonInput<KeyCode>) -> bool {
+    keyboard_input.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight])
+}
+
+// Tile just below the tower, where units are summoned
+pub fn home_tile(state: &GameState) -> TilePos {
+    let (x, y) = state.player_tower_location;
+    TilePos {
+        x,
+        y: y.saturating_sub(1),
+    }
+}
+
+// Tiles a chased unit can get from where the chaser is headed before it replans
+const CHASE_REPLAN_DISTANCE: u32 = 3;
+
+// Diagonals count as one, same as ENGAGE_RANGE
+pub(crate) fn tile_distance(a: &TilePos, b: &TilePos) -> u32 {
+    a.x.abs_diff(b.x).max(a.y.abs_diff(b.y))
+}
+
+// Nearest first, so the first unit in a group gets the tile that was clicked
+const FORMATION_RADIUS: i32 = 6;
+
+// Spreads a group around `center`, one tile each, only on ground that unit type
+// can stand on. Falls back to the centre when there's no room.
+pub fn formation(state: &GameState, center: TilePos, unit_types: &[UnitType]) -> Vec<TilePos> {
+    let mut offsets: Vec<(i32, i32)> = (-FORMATION_RADIUS..=FORMATION_RADIUS)
+        .flat_map(|dx| (-FORMATION_RADIUS..=FORMATION_RADIUS).map(move |dy| (dx, dy)))
+        .collect();
+    offsets.sort_by_key(|(dx, dy)| (dx * dx + dy * dy, *dy, *dx));
+
+    let (width, height) = state.map.size();
+    let mut taken: Vec<TilePos> = Vec::with_capacity(unit_types.len());
+
+    for unit_type in unit_types {
+        let tile = offsets
+            .iter()
+            .filter_map(|(dx, dy)| {
+                let (x, y) = (center.x as i32 + dx, center.y as i32 + dy);
+                if x < 0 || y < 0 || x >= width as i32 || y >= height as i32 {
+                    return None;
+                }
+                Some(TilePos {
+                    x: x as u32,
+                    y: y as u32,
+                })
+            })
+            .find(|tile| {
+                !taken.contains(tile) && terrain_cost(state, *unit_type, tile.x, tile.y).is_some()
+            })
+            .unwrap_or(center);
+        taken.push(tile);
+    }
+
+    taken
+}
+
+// Clicking a unit follows it (or attacks it, if it's not ours), alt-click patrols
+// between the end of the queue and the click, H heads home. Every selected unit
+// gets the order, moves and patrols are spread out with formation.
+pub fn issue_orders(
+    mut commands: Commands,
+    mut query: Query<(Entity, &TilePos, &Unit, &Slot, &mut UnitOrders)>,
+    others: Query<(Entity, &TilePos, Has<Enemy>), With<Unit>>,
+    cursor_pos: Res<CursorPos>,
+    mut ev_mapclick: EventReader<MapClick>,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    selected_unit: Res<SelectedUnit>,
+    state: Res<GameState>,
+) {
+    if selected_unit.units.is_empty() {
+        ev_mapclick.clear();
+        return;
+    }
+
+    let clicked = !ev_mapclick.is_empty();
+    ev_mapclick.clear();
+    let go_home = keyboard_input.just_pressed(KeyCode::KeyH);
+    if !clicked && !go_home {
+        return;
+    }
+
+    let append = is_appending(&keyboard_input);
+    let patrol = keyboard_input.any_pressed([KeyCode::AltLeft, KeyCode::AltRight]);
+    let destination = cursor_pos.tile_position;
+
+    let target = others
+        .iter()
+        .find(|(other, other_pos, _)| {
+            **other_pos == destination && !selected_unit.units.iter().any(|(_, e)| e == other)
+        })
+        .map(|(other, _, enemy)| (other, enemy));
+
+    let mut group: Vec<_> = query
+        .iter_mut()
+        .filter(|(_, _, _, slot, _)| selected_unit.is_selected(slot.slot))
+        .collect();
+    group.sort_by_key(|(_, _, _, slot, _)| slot.slot);
+
+    let unit_types: Vec<UnitType> = group
+        .iter()
+        .map(|(_, _, unit, _, _)| unit.unit_type)
+        .collect();
+    let spread = formation(&state, destination, &unit_types);
+
+    for ((e, tile_pos, _, slot, mut orders), spot) in group.into_iter().zip(spread) {
+        let order = match target {
+            _ if go_home => Order::ReturnToTower,
+            Some((other, true)) => Order::Attack(other),
+            Some((other, false)) => Order::Follow(other),
+            None if patrol => Order::Patrol {
+                from: if append {
+                    orders.end_tile(*tile_pos)
+                } else {
+                    *tile_pos
+                },
+                to: spot,
+            },
+            None => Order::Move(spot),
+        };
+
+        log::info!("Order for unit {}: {}", slot.slot, order);
+        give_order(&mut commands, e, &mut orders, order, append);
+    }
+}
+
+// Starts the next order when there's nothing to do, and notices when the current
+// one is done. Moving is done by MoveTo and digging by Digging, this just hands out
+// the work.
+pub fn advance_orders(
+    mut commands: Commands,
+    mut query: Query<(
+        Entity,
+        &TilePos,
+        &mut UnitOrders,
+        Has<MoveTo>,
+        Has<UnitPath>,
+        Has<Digging>,
+    )>,
+    targets: Query<&TilePos, With<Unit>>,
+    treasure_locs: Res<TreasureLocs>,
+    state: Res<GameState>,
+    mut ev_unreachable: EventReader<PathUnreachable>,
+) {
+    // Legs plan_paths gave up on last frame, they end with !moving like finished ones
+    let unreachable: HashSet<(Entity, TilePos)> = ev_unreachable
+        .read()
+        .map(|ev| (ev.unit, ev.destination))
+        .collect();
+
+    for (e, tile_pos, mut orders, move_to, path, digging) in query.iter_mut() {
+        let moving = move_to || path;
+
+        if let Some(order) = orders.current {
+            let done = match order {
+                Order::Move(_) | Order::ReturnToTower => !moving,
+                Order::Dig(_) => !digging,
+                Order::Patrol { from, to } => {
+                    // Walking back and forth to a tile we can't reach would never end
+                    if unreachable.contains(&(e, to)) {
+                        log::info!("Patrol to {:?} can't be reached, stopping", to);
+                    } else if !moving && orders.queue.is_empty() {
+                        orders.queue.push_back(Order::Patrol { from: to, to: from });
+                    }
+                    !moving
+                }
+                // Only done once the target is gone
+                Order::Attack(target) | Order::Follow(target) => match targets.get(target) {
+                    Ok(target_pos) => {
+                        // Attackers stop once they can fight, combat takes it from there
+                        let close_enough = match order {
+                            Order::Attack(_) => in_range(tile_pos, target_pos),
+                            _ => target_pos == tile_pos,
+                        };
+
+                        // Every replan is a full A*, so keep walking the old path
+                        // until the target has really moved away from it
+                        let replan = match orders.chasing {
+                            None => true,
+                            Some(chasing) => {
+                                tile_distance(&chasing, target_pos) > CHASE_REPLAN_DISTANCE
+                                    || (!moving && chasing != *target_pos)
+                            }
+                        };
+
+                        if close_enough {
+                            if moving && matches!(order, Order::Attack(_)) {
+                                orders.chasing = None;
+                                commands
+                                    .entity(e)
+                                    .remove::<(MoveTo, UnitPath, UnitDirection)>();
+                            }
+                        } else if replan {
+                            orders.chasing = Some(*target_pos);
+                            commands.entity(e).insert(MoveTo {
+                                destination: *target_pos,
+                            });
+                        }
+                        false
+                    }
+                    Err(_) => {
+                        commands
+                            .entity(e)
+                            .remove::<(MoveTo, UnitPath, UnitDirection)>();
+                        true
+                    }
+                },
+            };
+
+            if !done {
+                continue;
+            }
+            orders.finish();
+        }
+
+        let Some(order) = orders.queue.pop_front() else {
+            continue;
+        };
+
+        match order {
+            Order::Move(destination)
+            | Order::Patrol {
+                to: destination, ..
+            } => {
+                commands.entity(e).insert(MoveTo { destination });
+            }
+            Order::ReturnToTower => {
+                commands.entity(e).insert(MoveTo {
+                    destination: home_tile(&state),
+                });
+            }
+            Order::Dig(tile) if tile != *tile_pos => {
+                if orders.last == Some(Order::Move(tile)) {
+                    log::info!("Couldn't get to the dig at {:?}", tile);
+                    continue;
+                }
+
+                // Walk over first, then come back to the dig
+                orders.queue.push_front(order);
+                orders.current = Some(Order::Move(tile));
+                commands.entity(e).insert(MoveTo { destination: tile });
+                continue;
+            }
+            Order::Dig(tile) => {
+                if treasure_locs.locs.contains(&(tile.x, tile.y)) {
+                    commands.entity(e).insert(Digging::new());
+                } else {
+                    log::info!("Nothing to dig at {:?}", tile);
+                }
+            }
+            // Chasing starts next frame, once we know where the target is
+            Order::Attack(_) | Order::Follow(_) => {}
+        }
+
+        orders.current = Some(order);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+    use crate::map::fixtures::*;
+
+    const CENTER: TilePos = TilePos { x: 10, y: 10 };
+
+    #[test]
+    fn first_unit_gets_the_click() {
+        let state = flat_state(20, LOWLAND);
+        let spots = formation(&state, CENTER, &[UnitType::Scout; 5]);
+
+        assert_eq!(spots.len(), 5);
+        assert_eq!(spots[0], CENTER);
+        for (i, spot) in spots.iter().enumerate() {
+            assert!(!spots[i + 1..].contains(spot));
+            // Packed in as tight as it gets
+            assert!(tile_distance(spot, &CENTER) <= 1);
+        }
+    }
+
+    #[test]
+    fn only_where_each_unit_can_stand() {
+        let mut state = flat_state(20, LOWLAND);
+        state.biomes.set(10, 10, Biome::ShallowWater);
+        state.biomes.set(11, 10, Biome::DeepWater);
+
+        // Scouts wade, attack units don't, nobody swims
+        let spots = formation(&state, CENTER, &[UnitType::Scout, UnitType::Attack]);
+        assert_eq!(spots[0], CENTER);
+        assert_ne!(spots[1], CENTER);
+        assert_ne!(spots[1], TilePos { x: 11, y: 10 });
+    }
+
+    #[test]
+    fn edges_and_no_room() {
+        let state = flat_state(20, LOWLAND);
+        let corner = TilePos { x: 0, y: 0 };
+        let spots = formation(&state, corner, &[UnitType::Excavation; 4]);
+        assert_eq!(spots[0], corner);
+        assert!(spots.iter().all(|spot| spot.x < 20 && spot.y < 20));
+
+        // Nowhere to stand, everyone goes to the click
+        let mut state = flat_state(20, LOWLAND);
+        for y in 0..20 {
+            for x in 0..20 {
+                state.biomes.set(x, y, Biome::DeepWater);
+            }
+        }
+        let spots = formation(&state, CENTER, &[UnitType::Scout; 3]);
+        assert_eq!(spots, vec![CENTER; 3]);
+    }
+
+    // One selected unit in slot 0 clicking on a unit at the cursor
+    fn click_on_unit(enemy: bool) -> (Order, Entity) {
+        let mut world = World::new();
+        world.insert_resource(flat_state(20, LOWLAND));
+        world.insert_resource(CursorPos {
+            tile_position: CENTER,
+            ..Default::default()
+        });
+        world.init_resource::<ButtonInput<KeyCode>>();
+        world.init_resource::<Events<MapClick>>();
+
+        let ours = world
+            .spawn((
+                Unit::scout(),
+                TilePos { x: 5, y: 5 },
+                Slot { slot: 0 },
+                UnitOrders::default(),
+            ))
+            .id();
+        let mut clicked = world.spawn((Unit::attack(), CENTER));
+        if enemy {
+            clicked.insert(Enemy);
+        }
+        let clicked = clicked.id();
+
+        let mut selected_unit = SelectedUnit::default();
+        selected_unit.select(0, ours);
+        world.insert_resource(selected_unit);
+
+        world.send_event(MapClick(Vec2::ZERO));
+        world.run_system_once(issue_orders);
+
+        let orders = world.get::<UnitOrders>(ours).unwrap();
+        assert_eq!(orders.queue.len(), 1);
+        (orders.queue[0], clicked)
+    }
+
+    #[test]
+    fn clicking_enemies_attacks_them() {
+        let (order, clicked) = click_on_unit(true);
+        assert_eq!(order, Order::Attack(clicked));
+    }
+
+    #[test]
+    fn clicking_our_own_follows_them() {
+        let (order, clicked) = click_on_unit(false);
+        assert_eq!(order, Order::Follow(clicked));
+    }
+
+    // Orders left once a patrol leg has ended, or plan_paths gave up on it
+    fn end_patrol_leg(unreachable: bool) -> UnitOrders {
+        let mut world = World::new();
+        world.insert_resource(flat_state(20, LOWLAND));
+        world.insert_resource(TreasureLocs { locs: vec![] });
+        world.init_resource::<Events<PathUnreachable>>();
+
+        let (from, to) = (TilePos { x: 5, y: 5 }, CENTER);
+        let unit = world
+            .spawn((
+                Unit::scout(),
+                from,
+                UnitOrders {
+                    current: Some(Order::Patrol { from, to }),
+                    ..Default::default()
+                },
+            ))
+            .id();
+        if unreachable {
+            world.send_event(PathUnreachable {
+                unit,
+                destination: to,
+                error: PathError::Unreachable,
+            });
+        }
+
+        world.run_system_once(advance_orders);
+        world.entity_mut(unit).take::<UnitOrders>().unwrap()
+    }
+
+    #[test]
+    fn patrols_turn_around() {
+        let orders = end_patrol_leg(false);
+        assert_eq!(
+            orders.current,
+            Some(Order::Patrol {
+                from: CENTER,
+                to: TilePos { x: 5, y: 5 },
+            })
+        );
+    }
+
+    #[test]
+    fn unreachable_patrols_stop() {
+        let orders = end_patrol_leg(true);
+        assert!(orders.is_idle());
+    }
+}
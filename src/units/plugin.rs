@@ -1,6 +1,6 @@
+use std::collections::VecDeque;
+
 use bevy::prelude::*;
-use bevy_prng::WyRand;
-use bevy_rand::prelude::*;
 
 use crate::*;
 
@@ -22,6 +22,7 @@ impl Digging {
 impl<S: States> Plugin for UnitsPlugin<S> {
     fn build(&self, app: &mut App) {
         app.add_event::<AddUnitComplete>()
+            .add_event::<PathUnreachable>()
             // .add_systems(OnEnter(self.state.clone()), setup_units_bar)
             .add_systems(
                 PreUpdate,
@@ -31,17 +32,30 @@ impl<S: States> Plugin for UnitsPlugin<S> {
                 PreUpdate,
                 (prevent_collision).run_if(in_state(self.state.clone())),
             )
-            .add_systems(Update, set_direction.run_if(in_state(self.state.clone())))
+            .add_systems(
+                Update,
+                (
+                    issue_orders,
+                    advance_orders,
+                    replan_on_terrain_change.run_if(on_event::<TerrainChanged>()),
+                    plan_paths,
+                )
+                    .chain()
+                    .before(move_units)
+                    .run_if(in_state(self.state.clone())),
+            )
             .add_systems(
                 Update,
                 (unit_intersections, move_units).run_if(in_state(self.state.clone())),
             )
             .add_systems(Update, update_unit_pos.run_if(in_state(self.state.clone())))
+            .add_systems(Update, dig.run_if(in_state(self.state.clone())))
             .add_systems(
                 Update,
-                units_fog_of_war.run_if(in_state(self.state.clone())),
-            )
-            .add_systems(Update, dig.run_if(in_state(self.state.clone())));
+                (regrow_deposits, mine_deposits)
+                    .chain()
+                    .run_if(in_state(self.state.clone())),
+            );
     }
 }
 
@@ -207,6 +221,59 @@ pub fn dig(
     }
 }
 
+// Per point of excavation_speed, per second
+pub const MINING_RATE: f32 = 0.5;
+
+// No button needed, an excavation crew standing still on a deposit mines it
+fn mine_deposits(
+    mut commands: Commands,
+    query: Query<(&TilePos, &Unit), (Without<UnitDirection>, Without<Digging>)>,
+    mut gamestate: ResMut<GameState>,
+    mut deposits: ResMut<Deposits>,
+    time: Res<Time>,
+    mut stuff_q: Query<
+        &mut TileStorage,
+        (With<MapStuff>, Without<MapGround>, Without<MapFogOfWar>),
+    >,
+) {
+    for (tile_pos, unit) in query.iter() {
+        if !matches!(unit.unit_type, UnitType::Excavation) {
+            continue;
+        }
+
+        let Some(deposit) = deposits.at_mut((tile_pos.x, tile_pos.y)) else {
+            continue;
+        };
+
+        let wanted = unit.excavation_speed as f32 * MINING_RATE * time.delta_seconds();
+        let mined = deposit.mine(wanted);
+        gamestate.stockpile.add(deposit.kind, mined);
+    }
+
+    // Finished ore veins are gone for good, marker and all
+    let exhausted = deposits.remove_exhausted();
+    if exhausted.is_empty() {
+        return;
+    }
+
+    let Ok(mut stuff_tile_storage) = stuff_q.get_single_mut() else {
+        return;
+    };
+    for (x, y) in exhausted {
+        log::info!("Deposit at {:?} mined out", (x, y));
+        if let Some(tile_entity) = stuff_tile_storage.get(&TilePos { x, y }) {
+            stuff_tile_storage.remove(&TilePos { x, y });
+            commands.entity(tile_entity).despawn();
+        }
+    }
+}
+
+fn regrow_deposits(mut deposits: ResMut<Deposits>, time: Res<Time>) {
+    for deposit in deposits.deposits.iter_mut() {
+        deposit.regrow(time.delta_seconds());
+    }
+}
+
 fn unit_intersections(
     mut commands: Commands,
     query: Query<(Entity, &TilePos, &Unit, Option<&Digging>)>,
@@ -224,111 +291,169 @@ fn unit_intersections(
 
 fn update_unit_pos(
     mut q: Query<(&mut TilePos, &Transform, &Unit), Without<TileVisible>>,
-    tilemap_q: Query<(
-        &TilemapSize,
-        &TilemapGridSize,
-        &TilemapType,
-        &TileStorage,
-        &Transform,
-        &MapGround,
-    )>,
+    chunks: Res<MapChunks>,
 ) {
-    let (map_size, grid_size, map_type, tile_storage, map_transform, _) = tilemap_q.single();
     for (mut tile_pos, transform, _) in q.iter_mut() {
-        let cursor_in_map_pos: Vec2 = {
-            // Extend the cursor_pos vec3 by 0.0 and 1.0
-            let cursor_pos = Vec4::from((transform.translation.xy(), 0.0, 1.0));
-            let cursor_in_map_pos = map_transform.compute_matrix().inverse() * cursor_pos;
-            cursor_in_map_pos.xy()
-        };
         // Once we have a world position we can transform it into a possible tile position.
-        if let Some(new_tile_pos) =
-            TilePos::from_world_pos(&cursor_in_map_pos, map_size, grid_size, map_type)
-        {
+        if let Some(new_tile_pos) = chunks.world_to_tile(transform.translation.xy()) {
             *tile_pos = new_tile_pos;
         }
     }
 }
 
-fn units_fog_of_war(
-    q: Query<(Entity, &TilePos, &Unit, &UnitDirection)>,
-    fog_q: Query<(&MapFogOfWar, &TileStorage), Without<MapStuff>>,
-    mut tile_query: Query<&mut TileVisible>,
+// Turns move orders into a path, or reports why there isn't one
+fn plan_paths(
+    mut commands: Commands,
+    query: Query<(Entity, &TilePos, &Unit, &MoveTo)>,
+    state: Res<GameState>,
+    chunks: Res<MapChunks>,
+    mut ev_unreachable: EventWriter<PathUnreachable>,
 ) {
-    // todo make circular
-    let (_map_fog_of_war, fog_tile_storage) = fog_q.single();
-
-    for (_e, unit_tile_pos, unit, _) in q.iter() {
-        let radius = unit.visibility as i32;
-
-        for x in (unit_tile_pos.x as i32 - radius)..=(unit_tile_pos.x as i32 + radius) {
-            for y in (unit_tile_pos.y as i32 - radius)..=(unit_tile_pos.y as i32 + radius) {
-                let tile_pos = TilePos {
-                    x: x as u32,
-                    y: y as u32,
-                };
-                if let Some(tile_entity) = fog_tile_storage.get(&tile_pos) {
-                    let mut visibility = tile_query.get_mut(tile_entity).unwrap();
-                    visibility.0 = false;
-                }
+    for (e, tile_pos, unit, move_to) in query.iter() {
+        commands.entity(e).remove::<MoveTo>();
+
+        let path = find_path(&state, *tile_pos, move_to.destination, |x, y| {
+            terrain_cost(&state, unit.unit_type, x, y)
+        });
+
+        let mut waypoints: VecDeque<TilePos> = match path {
+            Ok(path) => path.into(),
+            Err(error) => {
+                log::warn!(
+                    "Unit can't get from {:?} to {:?}: {}",
+                    tile_pos,
+                    move_to.destination,
+                    error
+                );
+                commands.entity(e).remove::<(UnitPath, UnitDirection)>();
+                ev_unreachable.send(PathUnreachable {
+                    unit: e,
+                    destination: move_to.destination,
+                    error,
+                });
+                continue;
             }
-        }
+        };
+
+        // Already there
+        let Some(next) = waypoints.pop_front() else {
+            commands.entity(e).remove::<(UnitPath, UnitDirection)>();
+            continue;
+        };
+
+        commands.entity(e).insert((
+            UnitDirection {
+                direction: Vec2::ZERO,
+                destination: chunks.tile_to_world(&next),
+                destination_in_tile_pos: next,
+            },
+            UnitPath {
+                destination: move_to.destination,
+                waypoints,
+            },
+        ));
     }
 }
 
-// Need to give the unit a direction, then act on it later...
-fn set_direction(
+// Routes that pass this close to changed ground get planned again, diagonal steps
+// cut past the neighbours of a tile
+const REPLAN_RADIUS: u32 = 1;
+
+// The ground under a route may have changed, plan it again from where we are
+fn replan_on_terrain_change(
     mut commands: Commands,
-    mut query: Query<(Entity, &Transform, &Unit, &Slot)>,
-    cursor_pos: Res<CursorPos>,
-    mut ev_mapclick: EventReader<MapClick>,
-    selected_unit: Res<SelectedUnit>,
+    mut ev_changed: EventReader<TerrainChanged>,
+    query: Query<(Entity, &TilePos, &UnitPath, Option<&UnitDirection>)>,
 ) {
-    // todo Not sure if this is needed
-    if ev_mapclick.is_empty() || selected_unit.unit.is_none() {
-        return;
+    let changed: Vec<TilePos> = ev_changed.read().map(|ev| ev.tile).collect();
+
+    for (e, tile_pos, path, direction) in query.iter() {
+        let next = direction.map(|direction| direction.destination_in_tile_pos);
+        let route: Vec<TilePos> = next
+            .into_iter()
+            .chain(path.waypoints.iter().copied())
+            .collect();
+
+        if changed
+            .iter()
+            .any(|tile| passes_near(*tile_pos, &route, tile))
+        {
+            commands.entity(e).insert(MoveTo {
+                destination: path.destination,
+            });
+        }
     }
+}
 
-    let mapclick = ev_mapclick.read();
-
-    for (e, transform, _, slot) in query.iter_mut() {
-        if slot.slot != *selected_unit.unit.as_ref().unwrap() {
-            continue;
+// Waypoints are only where the route turns, so walk the straight runs between them
+fn passes_near(from: TilePos, waypoints: &[TilePos], tile: &TilePos) -> bool {
+    let step = |from: u32, to: u32, i: u32| {
+        if to >= from {
+            from + i.min(to - from)
+        } else {
+            from - i.min(from - to)
         }
-        log::info!("Setting direction for unit");
-        let direction = cursor_pos.tile_position_real - transform.translation.xy();
-        let direction = direction.normalize();
-        commands.entity(e).insert(UnitDirection {
-            direction: direction,
-            destination: cursor_pos.tile_position_real,
-            destination_in_tile_pos: cursor_pos.tile_position,
-        });
+    };
+
+    let mut prev = from;
+    for waypoint in waypoints.iter() {
+        for i in 0..=tile_distance(&prev, waypoint) {
+            let on_route = TilePos {
+                x: step(prev.x, waypoint.x, i),
+                y: step(prev.y, waypoint.y, i),
+            };
+            if tile_distance(&on_route, tile) <= REPLAN_RADIUS {
+                return true;
+            }
+        }
+        prev = *waypoint;
     }
+    false
 }
 
 pub const MOVEMENT_SPEED_SCALE: f32 = 20.0;
 
 fn move_units(
     mut commands: Commands,
-    mut query: Query<(Entity, &mut Transform, &Unit, &TilePos, &mut UnitDirection)>,
+    mut query: Query<(
+        Entity,
+        &mut Transform,
+        &Unit,
+        &TilePos,
+        &mut UnitDirection,
+        Option<&mut UnitPath>,
+    )>,
+    chunks: Res<MapChunks>,
+    state: Res<GameState>,
     time: Res<Time>,
 ) {
-    for (e, mut transform, unit, tilepos, mut unit_direction) in query.iter_mut() {
-        let direction = unit_direction.destination - transform.translation.xy();
-        unit_direction.direction = direction.normalize();
+    for (e, mut transform, unit, tilepos, mut unit_direction, path) in query.iter_mut() {
+        // Same table the path was planned with, somewhere it can't be (spawned
+        // in a river, say) it just gets out at normal speed
+        let cost = terrain_cost(&state, unit.unit_type, tilepos.x, tilepos.y).unwrap_or(1.0);
+        let step = unit.overworld_speed as f32 * time.delta_seconds() * MOVEMENT_SPEED_SCALE / cost;
+        let to_waypoint = unit_direction.destination - transform.translation.xy();
+
+        // Close enough to snap onto the waypoint this frame, then head for the next
+        if to_waypoint.length() > step {
+            unit_direction.direction = to_waypoint.normalize();
+            transform.translation.x += unit_direction.direction.x * step;
+            transform.translation.y += unit_direction.direction.y * step;
+            continue;
+        }
 
-        if *tilepos == unit_direction.destination_in_tile_pos {
-            log::info!("Unit has reached destination");
-            commands.entity(e).remove::<UnitDirection>();
-        } else {
-            transform.translation.x += unit_direction.direction.x
-                * unit.overworld_speed as f32
-                * time.delta_seconds()
-                * MOVEMENT_SPEED_SCALE;
-            transform.translation.y += unit_direction.direction.y
-                * unit.overworld_speed as f32
-                * time.delta_seconds()
-                * MOVEMENT_SPEED_SCALE;
+        transform.translation.x = unit_direction.destination.x;
+        transform.translation.y = unit_direction.destination.y;
+
+        match path.and_then(|mut path| path.waypoints.pop_front()) {
+            Some(next) => {
+                unit_direction.destination = chunks.tile_to_world(&next);
+                unit_direction.destination_in_tile_pos = next;
+            }
+            None => {
+                log::info!("Unit has reached destination");
+                commands.entity(e).remove::<(UnitDirection, UnitPath)>();
+            }
         }
     }
 }
@@ -372,7 +497,7 @@ fn prevent_collision(mut query: Query<(&mut Transform, &UnitVisual, Entity)>) {
 
 fn jitter_units(
     mut query: Query<(&mut Transform, &UnitVisual)>,
-    mut rng: ResMut<GlobalEntropy<WyRand>>,
+    mut rng: ResMut<CosmeticRng>,
 ) {
     for (mut transform, _) in query.iter_mut() {
         // Don't get more than 20 away from 0,0
@@ -404,9 +529,9 @@ fn jitter_units(
 fn spawn_sprites(
     mut commands: Commands,
     assets: Res<GameAssets>,
-    query: Query<(&Unit, Entity, Option<&Children>), With<UnitUninitialized>>,
+    query: Query<(&Unit, Entity, Option<&Children>, Has<Enemy>), With<UnitUninitialized>>,
 ) {
-    for (unit, entity, children) in query.iter() {
+    for (unit, entity, children, enemy) in query.iter() {
         let mut transform = Transform::from_translation(Vec3::ZERO);
         commands.entity(entity).remove::<UnitUninitialized>();
 
@@ -424,6 +549,10 @@ fn spawn_sprites(
                 transform.translation.y += 0.1;
                 p.spawn((
                     SpriteSheetBundle {
+                        sprite: Sprite {
+                            color: if enemy { ENEMY_UNIT_TINT } else { Color::WHITE },
+                            ..default()
+                        },
                         texture: assets.tiles.clone(),
                         atlas: TextureAtlas {
                             layout: assets.tiles_layout.clone(),
@@ -439,3 +568,25 @@ fn spawn_sprites(
         });
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_routes_passing_by_are_replanned() {
+        // Straight east, then diagonally up to (8, 3)
+        let from = TilePos { x: 0, y: 0 };
+        let route = [TilePos { x: 5, y: 0 }, TilePos { x: 8, y: 3 }];
+
+        // Between waypoints, next to the route and on the end
+        assert!(passes_near(from, &route, &TilePos { x: 2, y: 0 }));
+        assert!(passes_near(from, &route, &TilePos { x: 3, y: 1 }));
+        assert!(passes_near(from, &route, &TilePos { x: 7, y: 1 }));
+        assert!(passes_near(from, &route, &TilePos { x: 8, y: 3 }));
+
+        assert!(!passes_near(from, &route, &TilePos { x: 2, y: 2 }));
+        assert!(!passes_near(from, &route, &TilePos { x: 8, y: 0 }));
+        assert!(!passes_near(from, &[], &TilePos { x: 1, y: 1 }));
+    }
+}